@@ -0,0 +1,158 @@
+//! Cycle detection for state machines that are stepped over and over, like
+//! spinning a platform a billion times. Every state after the first `prefix`
+//! repeats with some `period`, so any far-off iteration maps back onto one
+//! we've already seen.
+
+use std::{collections::HashMap, hash::Hash};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Cycle {
+    /// How many steps it takes to first enter the cycle
+    pub prefix: usize,
+    /// How many steps it takes to go around the cycle once
+    pub period: usize,
+}
+
+impl Cycle {
+    /// The earliest iteration with the same state as iteration `n`
+    #[must_use]
+    pub fn reduce(&self, n: usize) -> usize {
+        if n < self.prefix {
+            n
+        } else {
+            self.prefix + (n - self.prefix) % self.period
+        }
+    }
+}
+
+/// Brent's algorithm: only ever keeps two states around, at the cost of
+/// stepping a bit more than `prefix + period` times. The state must
+/// eventually repeat, or this never returns.
+pub fn brent<S, F>(start: &S, mut step: F) -> Cycle
+where
+    S: Clone + Eq,
+    F: FnMut(&mut S),
+{
+    // Find the period: the tortoise teleports to the hare at every power of two
+    let mut power = 1;
+    let mut period = 1;
+    let mut tortoise = start.clone();
+    let mut hare = start.clone();
+    step(&mut hare);
+    while tortoise != hare {
+        if power == period {
+            tortoise = hare.clone();
+            power *= 2;
+            period = 0;
+        }
+        step(&mut hare);
+        period += 1;
+    }
+
+    // Then with the hare a whole period ahead, they meet at the start of it
+    let mut prefix = 0;
+    tortoise = start.clone();
+    hare = start.clone();
+    for _ in 0..period {
+        step(&mut hare);
+    }
+    while tortoise != hare {
+        step(&mut tortoise);
+        step(&mut hare);
+        prefix += 1;
+    }
+    Cycle { prefix, period }
+}
+
+/// Every state up to the first repeat, along with the cycle they form
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct History<S> {
+    pub cycle: Cycle,
+    states: Vec<S>,
+}
+
+impl<S> History<S> {
+    /// The state after `n` steps
+    #[must_use]
+    pub fn state_at(&self, n: usize) -> &S {
+        &self.states[self.cycle.reduce(n)]
+    }
+
+    /// The states in the order they were visited, from the start through one
+    /// full lap of the cycle
+    #[must_use]
+    pub fn states(&self) -> &[S] {
+        &self.states
+    }
+}
+
+/// Steps until a state repeats, remembering every state along the way. Takes
+/// more memory than `brent`, but steps exactly `prefix + period` times and
+/// can answer `state_at` for any iteration without stepping again.
+pub fn hashed<S, F>(start: S, mut step: F) -> History<S>
+where
+    S: Clone + Hash + Eq,
+    F: FnMut(&mut S),
+{
+    let mut seen = HashMap::new();
+    let mut states = vec![];
+    let mut state = start;
+    loop {
+        if let Some(&prefix) = seen.get(&state) {
+            let cycle = Cycle {
+                prefix,
+                period: states.len() - prefix,
+            };
+            return History { cycle, states };
+        }
+        seen.insert(state.clone(), states.len());
+        states.push(state.clone());
+        step(&mut state);
+    }
+}
+
+/// The state after `n` steps, using `brent` to skip all but at most
+/// `prefix + period` of them
+pub fn state_at<S, F>(start: &S, mut step: F, n: usize) -> S
+where
+    S: Clone + Eq,
+    F: FnMut(&mut S),
+{
+    let cycle = brent(start, &mut step);
+    let mut state = start.clone();
+    for _ in 0..cycle.reduce(n) {
+        step(&mut state);
+    }
+    state
+}
+
+#[cfg(test)]
+mod test {
+    use super::{brent, hashed, state_at, Cycle};
+
+    #[test]
+    pub fn rho() {
+        // 0 -> 1 -> 2 -> 3 -> 4 -> 5 -> 2 -> ...
+        let step = |s: &mut usize| *s = if *s == 5 { 2 } else { *s + 1 };
+        let expected = Cycle {
+            prefix: 2,
+            period: 4,
+        };
+        assert_eq!(brent(&0, step), expected);
+        let history = hashed(0, step);
+        assert_eq!(history.cycle, expected);
+        assert_eq!(*history.state_at(1_000_000_001), 5);
+        assert_eq!(state_at(&0, step, 1_000_000_001), 5);
+    }
+
+    #[test]
+    pub fn fixed_point() {
+        let step = |_: &mut usize| {};
+        let expected = Cycle {
+            prefix: 0,
+            period: 1,
+        };
+        assert_eq!(brent(&7, step), expected);
+        assert_eq!(hashed(7, step).cycle, expected);
+    }
+}
@@ -1,7 +1,12 @@
-use std::collections::HashMap;
+use std::{
+    collections::{HashMap, HashSet},
+    io::IsTerminal,
+};
 
 use aoc_runner_derive::{aoc, aoc_generator};
 
+use crate::day18::Polygon;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Tile {
     NS,
@@ -83,15 +88,6 @@ impl std::ops::Add<&Direction> for (usize, usize) {
     }
 }
 
-fn find_connection(tile: Tile, relationship: Direction) -> bool {
-    match relationship {
-        Direction::West => tile == Tile::EW || tile == Tile::NE || tile == Tile::SE,
-        Direction::East => tile == Tile::EW || tile == Tile::NW || tile == Tile::SW,
-        Direction::North => tile == Tile::NS || tile == Tile::SE || tile == Tile::SW,
-        Direction::South => tile == Tile::NS || tile == Tile::NE || tile == Tile::NW,
-    }
-}
-
 fn is_connected(start: Tile, end: Tile, relationship: Direction) -> bool {
     match relationship {
         Direction::West => {
@@ -164,79 +160,363 @@ fn where_next(posn: (usize, usize), tiles: &[Vec<Tile>]) -> Vec<Direction> {
     connected
 }
 
-fn traverse(start: (usize, usize), tiles: &[Vec<Tile>]) -> HashMap<(usize, usize), usize> {
-    let mut counts = HashMap::from([(start, 0usize)]);
-    let dirs = where_next(start, tiles);
-    // Once from each direction
+/// A closed loop of cells, in the order they're visited
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Loop {
+    vertices: Vec<(isize, isize)>,
+}
 
-    for start_dir in dirs {
+impl FromIterator<(isize, isize)> for Loop {
+    fn from_iter<T: IntoIterator<Item = (isize, isize)>>(iter: T) -> Self {
+        Loop {
+            vertices: iter.into_iter().collect(),
+        }
+    }
+}
+
+impl Loop {
+    /// Walks the pipes from `start` until we're back where we began. `start`
+    /// must already be its real tile, not `Tile::Start`.
+    #[must_use]
+    pub fn trace(start: (usize, usize), tiles: &[Vec<Tile>]) -> Self {
+        let mut vertices = vec![];
         let mut prev = start;
-        let mut dir = start_dir;
-        let mut seed = prev + &dir;
-        let mut count: usize = 0;
+        let mut seed = start;
         loop {
-            count += 1;
-            counts
-                .entry(seed)
-                .and_modify(|e| *e = *e.min(&mut count))
-                .or_insert(count);
-            dir = where_next(seed, tiles)
+            vertices.push((seed.0 as isize, seed.1 as isize));
+            let dir = where_next(seed, tiles)
                 .into_iter()
                 .find(|d| (seed + d) != prev)
                 .unwrap();
             prev = seed;
             seed += &dir;
-            if prev == start {
+            if seed == start {
                 break;
             }
         }
+        Loop { vertices }
+    }
+
+    #[must_use]
+    pub fn vertices(&self) -> &[(isize, isize)] {
+        &self.vertices
+    }
+
+    /// The number of cells on the loop itself
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.vertices.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.vertices.is_empty()
+    }
+
+    /// The loop as a polygon with a corner at every cell
+    #[must_use]
+    pub fn polygon(&self) -> Polygon {
+        self.vertices
+            .iter()
+            .map(|&(r, c)| (r as i128, c as i128))
+            .collect()
+    }
+
+    /// Twice the enclosed area, by the shoelace formula. Doubling keeps it exact.
+    #[must_use]
+    pub fn double_area(&self) -> usize {
+        self.polygon().double_area().try_into().unwrap()
+    }
+
+    /// Number of cells strictly inside the loop, by Pick's theorem
+    #[must_use]
+    pub fn num_contained(&self) -> usize {
+        self.polygon().interior().try_into().unwrap()
+    }
+
+    /// How far (in steps along the loop) the farthest cell is from the start
+    #[must_use]
+    pub fn farthest(&self) -> usize {
+        self.len() / 2
+    }
+}
+
+impl Direction {
+    fn opposite(self) -> Direction {
+        match self {
+            Direction::North => Direction::South,
+            Direction::South => Direction::North,
+            Direction::East => Direction::West,
+            Direction::West => Direction::East,
+        }
+    }
+}
+
+impl Tile {
+    fn openings(self) -> &'static [Direction] {
+        match self {
+            Tile::NS => &[Direction::North, Direction::South],
+            Tile::EW => &[Direction::East, Direction::West],
+            Tile::NE => &[Direction::North, Direction::East],
+            Tile::NW => &[Direction::North, Direction::West],
+            Tile::SW => &[Direction::South, Direction::West],
+            Tile::SE => &[Direction::South, Direction::East],
+            Tile::Ground | Tile::Start => &[],
+        }
+    }
+
+    fn box_char(self) -> char {
+        match self {
+            Tile::Ground => ' ',
+            Tile::NS => '│',
+            Tile::EW => '─',
+            Tile::NE => '└',
+            Tile::NW => '┘',
+            Tile::SW => '┐',
+            Tile::SE => '┌',
+            Tile::Start => 'S',
+        }
+    }
+}
+
+/// Like `posn + dir`, but `None` if that walks off the maze
+fn step(posn: (usize, usize), dir: Direction, tiles: &[Vec<Tile>]) -> Option<(usize, usize)> {
+    let (r, c) = match dir {
+        Direction::North => (posn.0.checked_sub(1)?, posn.1),
+        Direction::West => (posn.0, posn.1.checked_sub(1)?),
+        _ => posn + &dir,
+    };
+    tiles.get(r)?.get(c).map(|_| (r, c))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MazeError {
+    NoStart,
+    MultipleStarts(Vec<(usize, usize)>),
+    /// `S` has fewer than two neighbors pointing at it
+    DeadEndStart((usize, usize)),
+    /// `S` has more than two neighbors pointing at it, so we can't tell which
+    /// two make up the loop
+    AmbiguousStart((usize, usize)),
+}
+
+impl std::fmt::Display for MazeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MazeError::NoStart => write!(f, "no S in the maze"),
+            MazeError::MultipleStarts(starts) => write!(f, "multiple S in the maze: {starts:?}"),
+            MazeError::DeadEndStart(posn) => write!(f, "S at {posn:?} is a dead end"),
+            MazeError::AmbiguousStart(posn) => {
+                write!(f, "S at {posn:?} connects to more than two pipes")
+            }
+        }
     }
-    counts
 }
 
-fn find_start(tiles: &[Vec<Tile>]) -> ((usize, usize), Tile) {
-    for r in 0..tiles.len() {
-        for c in 0..tiles[r].len() {
-            if tiles[r][c] == Tile::Start {
-                let (north, south, east, west) = get_surrounding((r, c), tiles);
-                // I'll be connected to exactly two
-                let mut connected = vec![];
-                if let Some(west) = west {
-                    if find_connection(west, Direction::West) {
-                        connected.push(Direction::West);
-                    }
-                }
-                if let Some(east) = east {
-                    if find_connection(east, Direction::East) {
-                        connected.push(Direction::East);
-                    }
+impl std::error::Error for MazeError {}
+
+/// The result of checking a maze over: where `S` is and what it must be, the
+/// main loop if it closes, and any pipes that point into the loop without
+/// being part of it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Maze {
+    pub start: (usize, usize),
+    pub start_tile: Tile,
+    pub main_loop: Option<Loop>,
+    pub dangling: Vec<(usize, usize)>,
+}
+
+impl Maze {
+    pub fn validate(tiles: &[Vec<Tile>]) -> Result<Self, MazeError> {
+        let starts: Vec<_> = tiles
+            .iter()
+            .enumerate()
+            .flat_map(|(r, row)| {
+                row.iter()
+                    .enumerate()
+                    .filter(|(_, &t)| t == Tile::Start)
+                    .map(move |(c, _)| (r, c))
+            })
+            .collect();
+        let start = match starts[..] {
+            [] => return Err(MazeError::NoStart),
+            [start] => start,
+            _ => return Err(MazeError::MultipleStarts(starts)),
+        };
+
+        let mut connected: Vec<_> = [
+            Direction::North,
+            Direction::South,
+            Direction::East,
+            Direction::West,
+        ]
+        .into_iter()
+        .filter(|&d| {
+            step(start, d, tiles)
+                .is_some_and(|(r, c)| tiles[r][c].openings().contains(&d.opposite()))
+        })
+        .collect();
+        connected.sort();
+        let start_tile = match connected[..] {
+            [Direction::East, Direction::West] => Tile::EW,
+            [Direction::South, Direction::East] => Tile::SE,
+            [Direction::South, Direction::West] => Tile::SW,
+            [Direction::North, Direction::East] => Tile::NE,
+            [Direction::North, Direction::West] => Tile::NW,
+            [Direction::North, Direction::South] => Tile::NS,
+            [] | [_] => return Err(MazeError::DeadEndStart(start)),
+            _ => return Err(MazeError::AmbiguousStart(start)),
+        };
+        let tile_at = |(r, c): (usize, usize)| {
+            if (r, c) == start {
+                start_tile
+            } else {
+                tiles[r][c]
+            }
+        };
+
+        // Walk out of S until we either come back around or the pipes give out
+        let mut path = vec![start];
+        let mut dir = connected[0];
+        let mut closed = false;
+        while let Some(next) = step(*path.last().unwrap(), dir, tiles) {
+            let openings = tile_at(next).openings();
+            if !openings.contains(&dir.opposite()) {
+                break;
+            }
+            if next == start {
+                closed = true;
+                break;
+            }
+            dir = *openings.iter().find(|&&o| o != dir.opposite()).unwrap();
+            path.push(next);
+        }
+
+        let on_path: HashSet<_> = path.iter().copied().collect();
+        let mut dangling: Vec<_> = path
+            .iter()
+            .flat_map(|&p| {
+                [
+                    Direction::North,
+                    Direction::South,
+                    Direction::East,
+                    Direction::West,
+                ]
+                .into_iter()
+                .filter_map(move |d| step(p, d, tiles).map(|n| (d, n)))
+            })
+            .filter(|&(d, n)| {
+                !on_path.contains(&n) && tiles[n.0][n.1].openings().contains(&d.opposite())
+            })
+            .map(|(_, n)| n)
+            .collect();
+        dangling.sort_unstable();
+        dangling.dedup();
+
+        let main_loop = closed.then(|| {
+            path.iter()
+                .map(|&(r, c)| (r as isize, c as isize))
+                .collect()
+        });
+        Ok(Maze {
+            start,
+            start_tile,
+            main_loop,
+            dangling,
+        })
+    }
+
+    #[must_use]
+    pub fn is_closed(&self) -> bool {
+        self.main_loop.is_some()
+    }
+
+    /// A printable view of the maze that only draws the main loop
+    #[must_use]
+    pub fn view<'a>(&'a self, tiles: &'a [Vec<Tile>]) -> MazeView<'a> {
+        MazeView {
+            maze: self,
+            tiles,
+            inside: true,
+            color: false,
+        }
+    }
+}
+
+/// Draws the main loop with box-drawing characters and blanks out everything
+/// else. Cells inside the loop are marked with `I`, and with color on, each
+/// loop cell is shaded from blue (near `S`) to red (farthest from `S`).
+#[derive(Debug, Clone, Copy)]
+pub struct MazeView<'a> {
+    maze: &'a Maze,
+    tiles: &'a [Vec<Tile>],
+    inside: bool,
+    color: bool,
+}
+
+impl MazeView<'_> {
+    #[must_use]
+    pub fn inside(self, inside: bool) -> Self {
+        MazeView { inside, ..self }
+    }
+
+    #[must_use]
+    pub fn color(self, color: bool) -> Self {
+        MazeView { color, ..self }
+    }
+
+    /// Turns on color only if `stream` is a terminal, so files stay plain text
+    #[must_use]
+    pub fn color_for(self, stream: &impl IsTerminal) -> Self {
+        self.color(stream.is_terminal())
+    }
+}
+
+impl std::fmt::Display for MazeView<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (distances, farthest): (HashMap<_, _>, _) =
+            self.maze
+                .main_loop
+                .as_ref()
+                .map_or_else(Default::default, |l| {
+                    let len = l.len();
+                    let distances = l
+                        .vertices()
+                        .iter()
+                        .enumerate()
+                        .map(|(i, &(r, c))| ((r as usize, c as usize), i.min(len - i)))
+                        .collect();
+                    (distances, l.farthest().max(1))
+                });
+        for (r, row) in self.tiles.iter().enumerate() {
+            let mut inside = false;
+            for (c, &tile) in row.iter().enumerate() {
+                let Some(&dist) = distances.get(&(r, c)) else {
+                    f.write_str(if inside && self.inside { "I" } else { " " })?;
+                    continue;
+                };
+                let tile = if tile == Tile::Start {
+                    self.maze.start_tile
+                } else {
+                    tile
+                };
+                // Crossing anything that heads north flips whether we're inside
+                if tile.openings().contains(&Direction::North) {
+                    inside = !inside;
                 }
-                if let Some(north) = north {
-                    if find_connection(north, Direction::North) {
-                        connected.push(Direction::North);
-                    }
+                if self.color {
+                    let red = 255 * dist / farthest;
+                    write!(f, "\x1b[38;2;{red};64;{}m", 255 - red)?;
                 }
-                if let Some(south) = south {
-                    if find_connection(south, Direction::South) {
-                        connected.push(Direction::South);
-                    }
+                write!(f, "{}", tile.box_char())?;
+                if self.color {
+                    f.write_str("\x1b[0m")?;
                 }
-                connected.sort();
-                let result = match (connected[0], connected[1]) {
-                    (Direction::East, Direction::West) => Tile::EW,
-                    (Direction::South, Direction::East) => Tile::SE,
-                    (Direction::South, Direction::West) => Tile::SW,
-                    (Direction::North, Direction::East) => Tile::NE,
-                    (Direction::North, Direction::West) => Tile::NW,
-                    (Direction::North, Direction::South) => Tile::NS,
-                    (_, _) => unreachable!(),
-                };
-
-                return ((r, c), result);
             }
+            writeln!(f)?;
         }
+        Ok(())
     }
-    unreachable!()
 }
 
 #[aoc_generator(day10)]
@@ -249,21 +529,18 @@ pub fn gen(input: &str) -> Vec<Vec<Tile>> {
 
 #[aoc(day10, part1)]
 pub fn part1(tiles: &[Vec<Tile>]) -> usize {
-    let ((r, c), t) = find_start(tiles);
-    let mut tiles = tiles.to_owned();
-    tiles[r][c] = t;
-    *traverse((r, c), &tiles).values().max().unwrap()
+    let maze = Maze::validate(tiles).unwrap();
+    maze.main_loop
+        .expect("the main loop isn't closed")
+        .farthest()
 }
 
 #[aoc(day10, part2)]
 pub fn part2(tiles: &[Vec<Tile>]) -> usize {
-    let ((r, c), t) = find_start(tiles);
-    let mut tiles = tiles.to_owned();
-    tiles[r][c] = t;
-    let counts = traverse((r, c), &tiles);
-    let path: Vec<_> = counts.keys().map(|&(r, c)| ((r, c), tiles[r][c])).collect();
-    let grid = Grid::from(path);
-    grid.num_contained()
+    let maze = Maze::validate(tiles).unwrap();
+    maze.main_loop
+        .expect("the main loop isn't closed")
+        .num_contained()
 }
 
 #[derive(Clone)]
@@ -293,7 +570,7 @@ impl Grid {
         let mut count = 0;
         for r in 0..self.grid.len() {
             for c in 0..self.grid[r].len() {
-                if self.grid[r][c] == Tile::Ground && self.is_inside((r, c)) {
+                if self.is_inside((r, c)) {
                     count += 1;
                 }
             }
@@ -352,3 +629,123 @@ impl std::fmt::Debug for Grid {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{gen, part1, part2, Grid, Loop, Maze, MazeError, Tile};
+
+    const LARGER: &str = "...........
+.S-------7.
+.|F-----7|.
+.||.....||.
+.||.....||.
+.|L-7.F-J|.
+.|..|.|..|.
+.L--J.L--J.
+...........";
+
+    #[test]
+    pub fn examples() {
+        assert_eq!(part1(&gen("..F7.\n.FJ|.\nSJ.L7\n|F--J\nLJ...")), 8);
+        assert_eq!(part2(&gen(LARGER)), 4);
+        let messy = "FF7FSF7F7F7F7F7F---7
+L|LJ||||||||||||F--J
+FL-7LJLJ||||||LJL-77
+F--JF--7||LJLJ7F7FJ-
+L---JF-JLJ.||-FJLJJ7
+|F|F-JF---7F7-L7L|7|
+|FFJF7L7F-JF7|JL---7
+7-L-JL7||F7|L7F-7F7|
+L.L7LFJ|||||FJL7||LJ
+L7JLJL-JLJLJL--JLJ.L";
+        assert_eq!(part2(&gen(messy)), 10);
+    }
+
+    #[test]
+    pub fn grid_matches_loop() {
+        let mut tiles = gen(LARGER);
+        tiles[1][1] = Tile::SE;
+        let main_loop = Loop::trace((1, 1), &tiles);
+        assert_eq!(main_loop.len(), 46);
+        assert_eq!(main_loop.num_contained(), 4);
+        let grid = Grid::from(main_loop.vertices().iter().map(|&(r, c)| {
+            let (r, c) = (r as usize, c as usize);
+            ((r, c), tiles[r][c])
+        }));
+        assert_eq!(grid.num_contained(), 4);
+        assert_eq!(grid.to_string().matches('I').count(), 4);
+    }
+
+    #[test]
+    pub fn grid_with_strays() {
+        // A loop with a stray pipe inside it, and a second loop beside it
+        let grid = Grid::from(
+            gen("F--7F7\n|.-|LJ\n|..|..\nL--J..")
+                .into_iter()
+                .enumerate()
+                .flat_map(|(r, row)| row.into_iter().enumerate().map(move |(c, t)| ((r, c), t))),
+        );
+        assert_eq!(grid.num_contained(), 3);
+    }
+
+    #[test]
+    pub fn validate() {
+        let maze = Maze::validate(&gen(".....\n.S-7.\n-|.|.\n.L-J.\n.....")).unwrap();
+        assert!(maze.is_closed());
+        assert_eq!(maze.start, (1, 1));
+        assert_eq!(maze.start_tile, Tile::SE);
+        assert_eq!(maze.main_loop.unwrap().len(), 8);
+        assert_eq!(maze.dangling, vec![(2, 0)]);
+
+        let open = Maze::validate(&gen(".S-7.\n.|.|.\n.L-..")).unwrap();
+        assert!(!open.is_closed());
+    }
+
+    #[test]
+    pub fn validate_errors() {
+        assert_eq!(Maze::validate(&gen("F7\nLJ")), Err(MazeError::NoStart));
+        assert_eq!(
+            Maze::validate(&gen("S-S")),
+            Err(MazeError::MultipleStarts(vec![(0, 0), (0, 2)]))
+        );
+        assert_eq!(
+            Maze::validate(&gen("S....")),
+            Err(MazeError::DeadEndStart((0, 0)))
+        );
+        assert_eq!(
+            Maze::validate(&gen("S-7\n..|")),
+            Err(MazeError::DeadEndStart((0, 0)))
+        );
+        assert_eq!(
+            Maze::validate(&gen(".|...\n.S-7.\n.|.|.\n.L-J.")),
+            Err(MazeError::AmbiguousStart((1, 1)))
+        );
+    }
+
+    #[test]
+    pub fn view() {
+        let tiles = gen(LARGER);
+        let maze = Maze::validate(&tiles).unwrap();
+        let plain = maze.view(&tiles).to_string();
+        assert_eq!(plain.matches('I').count(), 4);
+        assert_eq!(plain.lines().nth(1), Some(" ┌───────┐ "));
+        assert_eq!(plain.lines().nth(6), Some(" │II│ │II│ "));
+        assert!(!maze.view(&tiles).inside(false).to_string().contains('I'));
+
+        // Every colored cell is a single character, reset right after
+        let colored = maze.view(&tiles).color(true).to_string();
+        let mut stripped = String::new();
+        let mut rest = colored.as_str();
+        while let Some(i) = rest.find("\x1b[38;2;") {
+            stripped.push_str(&rest[..i]);
+            rest = &rest[i..];
+            let m = rest.find('m').unwrap();
+            let mut chars = rest[m + 1..].chars();
+            let cell = chars.next().unwrap();
+            stripped.push(cell);
+            rest = chars.as_str().strip_prefix("\x1b[0m").unwrap();
+        }
+        stripped.push_str(rest);
+        assert_eq!(stripped, plain);
+    }
+}
@@ -1,35 +1,188 @@
+use std::collections::BTreeMap;
+
 use aoc_runner_derive::{aoc, aoc_generator};
 
-fn expand(galaxies: &[(usize, usize)], replace: usize) -> Vec<(usize, usize)> {
-    let occ_rows: Vec<_> = galaxies.iter().map(|&g| g.0).collect();
-    let occ_cols: Vec<_> = galaxies.iter().map(|&g| g.1).collect();
-    let max_rows = *occ_rows.iter().max().unwrap();
-    let max_cols = *occ_cols.iter().max().unwrap();
-    let mut exp_rows: Vec<_> = (0..=max_rows).filter(|r| !occ_rows.contains(r)).collect();
-    let mut exp_cols: Vec<_> = (0..=max_cols).filter(|c| !occ_cols.contains(c)).collect();
-    exp_rows.sort_unstable();
-    exp_cols.sort_unstable();
-
-    let mut out = galaxies.to_vec();
-    // Now, for each expanded row, add an extra row to anyone greater than me
-    out.sort_by_key(|&(r, _)| r);
-    for row in exp_rows.into_iter().rev() {
-        for e in out.iter_mut().skip_while(|(r, _)| r < &row) {
-            e.0 += replace - 1;
+/// Counts the empty lines before each coordinate. A line is empty if no galaxy
+/// sits on it; `occupied` must be sorted and deduplicated.
+fn empties_before(coord: usize, occupied: &[usize]) -> usize {
+    coord - occupied.partition_point(|&o| o < coord)
+}
+
+/// Replaces every empty row and column with `num / den` of them, in units of
+/// `1 / den` of a line so everything stays a whole number. Factors below 1
+/// shrink the empty space instead.
+fn expand(galaxies: &[(usize, usize)], num: u64, den: u64) -> Vec<(u64, u64)> {
+    assert!(den > 0, "can't expand by a factor with no denominator");
+    let mut occ_rows: Vec<_> = galaxies.iter().map(|&g| g.0).collect();
+    let mut occ_cols: Vec<_> = galaxies.iter().map(|&g| g.1).collect();
+    occ_rows.sort_unstable();
+    occ_rows.dedup();
+    occ_cols.sort_unstable();
+    occ_cols.dedup();
+
+    // Occupied lines count `den` each and empty ones `num`
+    let scale = |coord: usize, occupied: &[usize]| {
+        let empty = empties_before(coord, occupied) as u64;
+        (coord as u64 - empty) * den + empty * num
+    };
+    galaxies
+        .iter()
+        .map(|&(r, c)| (scale(r, &occ_rows), scale(c, &occ_cols)))
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Metric {
+    #[default]
+    Manhattan,
+    Chebyshev,
+    Euclidean,
+}
+
+/// The distance between two galaxies, in the same units as their coordinates
+fn dist(p1: &(u64, u64), p2: &(u64, u64), metric: Metric) -> f64 {
+    let dr = p1.0.abs_diff(p2.0) as f64;
+    let dc = p1.1.abs_diff(p2.1) as f64;
+    match metric {
+        Metric::Manhattan => dr + dc,
+        Metric::Chebyshev => dr.max(dc),
+        Metric::Euclidean => dr.hypot(dc),
+    }
+}
+
+/// Sum of `|a - b|` over every pair, by sorting and keeping a running total of
+/// everything before each coordinate
+fn axis_sum(mut coords: Vec<i128>) -> u128 {
+    coords.sort_unstable();
+    let mut prefix = 0;
+    let mut total = 0;
+    for (i, x) in coords.into_iter().enumerate() {
+        total += x * i as i128 - prefix;
+        prefix += x;
+    }
+    total as u128
+}
+
+/// The galaxies after the universe has expanded. Coordinates are in units of
+/// `1 / scale` of a line, which is 1 unless the expansion factor was a
+/// fraction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GalaxyMap {
+    galaxies: Vec<(u64, u64)>,
+    scale: u64,
+}
+
+impl GalaxyMap {
+    /// Every empty line becomes `factor` of them
+    #[must_use]
+    pub fn new(galaxies: &[(usize, usize)], factor: u64) -> Self {
+        Self::with_ratio(galaxies, factor, 1)
+    }
+
+    /// Every empty line becomes `num / den` of them
+    #[must_use]
+    pub fn with_ratio(galaxies: &[(usize, usize)], num: u64, den: u64) -> Self {
+        GalaxyMap {
+            galaxies: expand(galaxies, num, den),
+            scale: den,
         }
     }
-    // Now, for each expanded row, add an extra row to anyone greater than me
-    out.sort_by_key(|&(_, c)| c);
-    for col in exp_cols.into_iter().rev() {
-        for e in out.iter_mut().skip_while(|(_, c)| c < &col) {
-            e.1 += replace - 1;
+
+    #[must_use]
+    pub fn galaxies(&self) -> &[(u64, u64)] {
+        &self.galaxies
+    }
+
+    #[must_use]
+    pub fn scale(&self) -> u64 {
+        self.scale
+    }
+
+    /// The sum of the Manhattan distances between every pair of galaxies, in
+    /// units of `1 / scale`. The axes are independent, so each is handled on
+    /// its own in O(n log n).
+    #[must_use]
+    pub fn distance_sum(&self) -> u128 {
+        axis_sum(self.galaxies.iter().map(|g| g.0.into()).collect())
+            + axis_sum(self.galaxies.iter().map(|g| g.1.into()).collect())
+    }
+
+    /// Like `distance_sum`, but under any metric, and in whole lines.
+    /// Chebyshev distance is just Manhattan distance on the grid rotated 45
+    /// degrees (and doubled), so it's as fast; Euclidean has no such trick and
+    /// checks every pair.
+    #[must_use]
+    pub fn distance_sum_by(&self, metric: Metric) -> f64 {
+        let sum = match metric {
+            Metric::Manhattan => self.distance_sum() as f64,
+            Metric::Chebyshev => {
+                let (u, v) = self
+                    .galaxies
+                    .iter()
+                    .map(|&(r, c)| (i128::from(r) + i128::from(c), i128::from(r) - i128::from(c)))
+                    .unzip();
+                ((axis_sum(u) + axis_sum(v)) / 2) as f64
+            }
+            Metric::Euclidean => self.pairs(metric).map(|(_, _, d)| d).sum(),
+        };
+        sum / self.scale as f64
+    }
+
+    /// Every unordered pair of galaxies, as `(i, j, distance)` with `i < j`
+    pub fn pairs(&self, metric: Metric) -> impl Iterator<Item = (usize, usize, f64)> + '_ {
+        let g = &self.galaxies;
+        (0..g.len())
+            .flat_map(move |i| ((i + 1)..g.len()).map(move |j| (i, j, self.dist(i, j, metric))))
+    }
+
+    /// The distance between galaxies `i` and `j`, in whole lines
+    fn dist(&self, i: usize, j: usize, metric: Metric) -> f64 {
+        dist(&self.galaxies[i], &self.galaxies[j], metric) / self.scale as f64
+    }
+
+    /// All the other galaxies, with their distance from galaxy `i`
+    fn others(&self, i: usize, metric: Metric) -> impl Iterator<Item = (usize, f64)> + '_ {
+        (0..self.galaxies.len())
+            .filter(move |&j| j != i)
+            .map(move |j| (j, self.dist(i, j, metric)))
+    }
+
+    /// The galaxy closest to galaxy `i`, or `None` if it's alone
+    #[must_use]
+    pub fn nearest(&self, i: usize, metric: Metric) -> Option<(usize, f64)> {
+        self.others(i, metric).min_by(|a, b| a.1.total_cmp(&b.1))
+    }
+
+    /// The galaxy farthest from galaxy `i`, or `None` if it's alone
+    #[must_use]
+    pub fn farthest(&self, i: usize, metric: Metric) -> Option<(usize, f64)> {
+        self.others(i, metric).max_by(|a, b| a.1.total_cmp(&b.1))
+    }
+
+    /// The `k` closest pairs, closest first
+    #[must_use]
+    pub fn closest_pairs(&self, k: usize, metric: Metric) -> Vec<(usize, usize, f64)> {
+        let mut pairs: Vec<_> = self.pairs(metric).collect();
+        if k < pairs.len() {
+            pairs.select_nth_unstable_by(k, |a, b| a.2.total_cmp(&b.2));
+            pairs.truncate(k);
         }
+        pairs.sort_by(|a, b| a.2.total_cmp(&b.2));
+        pairs
     }
-    out
-}
 
-fn dist(p1: &(usize, usize), p2: &(usize, usize)) -> usize {
-    (p1.0.max(p2.0) - p1.0.min(p2.0)) + (p1.1.max(p2.1) - p1.1.min(p2.1))
+    /// Counts pairwise distances in buckets of `width`; bucket `b` holds
+    /// distances in `[b * width, (b + 1) * width)`. Panics unless `width` is
+    /// positive.
+    #[must_use]
+    pub fn histogram(&self, width: f64, metric: Metric) -> BTreeMap<usize, usize> {
+        assert!(width > 0.0, "histogram buckets need a positive width");
+        let mut hist = BTreeMap::new();
+        for (_, _, d) in self.pairs(metric) {
+            *hist.entry((d / width) as usize).or_default() += 1;
+        }
+        hist
+    }
 }
 
 #[aoc_generator(day11)]
@@ -47,23 +200,111 @@ pub fn gen(input: &str) -> Vec<(usize, usize)> {
 
 #[aoc(day11, part1)]
 pub fn part1(galaxies: &[(usize, usize)]) -> usize {
-    let shifted = expand(galaxies, 2);
-    let mut dists = vec![];
-    for g1 in 0..shifted.len() {
-        for g2 in (g1 + 1)..shifted.len() {
-            dists.push(dist(&shifted[g1], &shifted[g2]));
-        }
-    }
-    dists.iter().sum()
+    GalaxyMap::new(galaxies, 2)
+        .distance_sum()
+        .try_into()
+        .unwrap()
 }
 
+#[aoc(day11, part2)]
 pub fn part2(galaxies: &[(usize, usize)]) -> usize {
-    let shifted = expand(galaxies, 1_000_000);
-    let mut dists = vec![];
-    for g1 in 0..shifted.len() {
-        for g2 in (g1 + 1)..shifted.len() {
-            dists.push(dist(&shifted[g1], &shifted[g2]));
+    GalaxyMap::new(galaxies, 1_000_000)
+        .distance_sum()
+        .try_into()
+        .unwrap()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{gen, part1, GalaxyMap, Metric};
+
+    const EXAMPLE: &str = "...#......
+.......#..
+#.........
+..........
+......#...
+.#........
+.........#
+..........
+.......#..
+#...#.....";
+
+    #[test]
+    pub fn expansion() {
+        let galaxies = gen(EXAMPLE);
+        assert_eq!(part1(&galaxies), 374);
+        assert_eq!(GalaxyMap::new(&galaxies, 10).distance_sum(), 1030);
+        assert_eq!(GalaxyMap::new(&galaxies, 100).distance_sum(), 8410);
+        // No expansion at all is a factor of 1, and the sum grows linearly
+        assert_eq!(GalaxyMap::new(&galaxies, 1).distance_sum(), 292);
+        assert_eq!(
+            GalaxyMap::new(&galaxies, 1_000_000_000).distance_sum(),
+            292 + 82 * 999_999_999
+        );
+    }
+
+    #[test]
+    pub fn ratios() {
+        let galaxies = gen(EXAMPLE);
+        let half = GalaxyMap::with_ratio(&galaxies, 1, 2);
+        assert_eq!(half.scale(), 2);
+        // 292 - 82 / 2 lines, in half lines
+        assert_eq!(half.distance_sum(), 502);
+        let whole = GalaxyMap::with_ratio(&galaxies, 6, 3);
+        assert_eq!(whole.distance_sum(), 374 * 3);
+        // Empty lines can vanish completely
+        let gone = GalaxyMap::with_ratio(&galaxies, 0, 1);
+        assert_eq!(gone.distance_sum(), 292 - 82);
+    }
+
+    #[test]
+    pub fn metrics() {
+        let map = GalaxyMap::new(&gen(EXAMPLE), 2);
+        for metric in [Metric::Manhattan, Metric::Chebyshev, Metric::Euclidean] {
+            let brute: f64 = map.pairs(metric).map(|(_, _, d)| d).sum();
+            assert!(
+                (brute - map.distance_sum_by(metric)).abs() < 1e-6,
+                "{metric:?}"
+            );
         }
+        // Galaxies 5 and 9 in the puzzle text
+        let (_, _, d) = map
+            .pairs(Metric::Manhattan)
+            .find(|p| (p.0, p.1) == (4, 8))
+            .unwrap();
+        assert_eq!(d, 9.0);
+        let (_, _, d) = map
+            .pairs(Metric::Chebyshev)
+            .find(|p| (p.0, p.1) == (4, 8))
+            .unwrap();
+        assert_eq!(d, 5.0);
+        let half = GalaxyMap::with_ratio(&gen(EXAMPLE), 1, 2);
+        let brute: f64 = half.pairs(Metric::Chebyshev).map(|(_, _, d)| d).sum();
+        assert!((brute - half.distance_sum_by(Metric::Chebyshev)).abs() < 1e-6);
+    }
+
+    #[test]
+    pub fn neighbors() {
+        let map = GalaxyMap::new(&gen(EXAMPLE), 2);
+        let mut all: Vec<_> = map.pairs(Metric::Manhattan).map(|(_, _, d)| d).collect();
+        all.sort_by(f64::total_cmp);
+        let closest = map.closest_pairs(3, Metric::Manhattan);
+        assert_eq!(closest.iter().map(|p| p.2).collect::<Vec<_>>(), all[..3]);
+        assert_eq!(map.closest_pairs(100, Metric::Manhattan).len(), 36);
+
+        assert_eq!(map.nearest(0, Metric::Manhattan), Some((1, 6.0)));
+        assert_eq!(map.farthest(0, Metric::Manhattan), Some((7, 15.0)));
+        let alone = GalaxyMap::new(&[(0, 0)], 2);
+        assert_eq!(alone.nearest(0, Metric::Manhattan), None);
+
+        let hist = map.histogram(5.0, Metric::Manhattan);
+        assert_eq!(hist.values().sum::<usize>(), 36);
+        assert_eq!(hist.keys().next(), Some(&1));
+    }
+
+    #[test]
+    #[should_panic(expected = "positive width")]
+    pub fn histogram_width() {
+        let _ = GalaxyMap::new(&gen(EXAMPLE), 2).histogram(0.0, Metric::Manhattan);
     }
-    dists.iter().sum()
 }
@@ -1,13 +1,14 @@
 use aoc_runner_derive::{aoc, aoc_generator};
-use std::{collections::HashMap, fmt::Display, str::FromStr};
+use std::{fmt::Display, str::FromStr};
 
+use rand::Rng;
 use rayon::{
     iter::{IntoParallelRefIterator, ParallelIterator},
     str,
 };
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-enum Spring {
+pub enum Spring {
     Operational,
     Damaged,
     Unknown,
@@ -45,105 +46,271 @@ pub struct Record {
 }
 
 impl Record {
-    pub fn possibilities(&self) -> usize {
-        if self.springs.is_empty() {
-            usize::from(self.groups.is_empty())
-        } else if self.groups.is_empty() {
-            usize::from(!self.springs.contains(&Spring::Damaged))
-        } else {
-            // At this point, we need to head to the base case.
-            let mut result = 0;
-            let head = self.springs.first().unwrap();
-            if matches!(head, Spring::Unknown | Spring::Operational) {
-                // If I'm operational, (or one side of the don't know), then no group is affected.
-                // So in this case it's just the possibilities of everything to the right
-                let mut clone = self.clone();
-                clone.springs = clone.springs.into_iter().skip(1).collect();
-                result += clone.possibilities();
+    /// Given `next[i]`, the ways to place the groups after this one in springs
+    /// `i..`, fills in `ways[i]`, the ways to place a group of length `g` and
+    /// everything after it in springs `i..`.
+    fn fill_row(&self, g: usize, next: &[u128], ways: &mut [u128]) {
+        let n = self.springs.len();
+        ways[n] = 0;
+        // How many springs starting at i could all be damaged
+        let mut run = 0;
+        for i in (0..n).rev() {
+            let spring = self.springs[i];
+            run = if spring == Spring::Operational {
+                0
+            } else {
+                run + 1
+            };
+            let mut total = 0;
+            if spring != Spring::Damaged {
+                total += ways[i + 1];
             }
+            if self.fits(i, g, run) {
+                // Consume the group here, plus the operational one after it
+                total += next[(i + g + 1).min(n)];
+            }
+            ways[i] = total;
+        }
+    }
+
+    /// Whether a group of length `g` can start at spring `i`, given the run of
+    /// maybe-damaged springs starting there
+    fn fits(&self, i: usize, g: usize, run: usize) -> bool {
+        self.springs[i] != Spring::Operational
+            && run >= g
+            && self.springs.get(i + g) != Some(&Spring::Damaged)
+    }
+
+    /// The row for when every group has been placed: we're fine as long as
+    /// nothing after is damaged
+    fn last_row(&self) -> Vec<u128> {
+        let n = self.springs.len();
+        let mut row = vec![0u128; n + 1];
+        row[n] = 1;
+        for i in (0..n).rev() {
+            if self.springs[i] != Spring::Damaged {
+                row[i] = row[i + 1];
+            }
+        }
+        row
+    }
+
+    /// Counts the arrangements consistent with the record. `ways[i]` is the
+    /// number of ways to place groups `g..` in springs `i..`; we fill it in
+    /// for each group from the last to the first, only ever keeping the row
+    /// for `g` and the row for `g + 1`. That's O(springs * groups) time, and
+    /// no allocation beyond the two rows.
+    #[must_use]
+    pub fn possibilities(&self) -> u128 {
+        let mut next = self.last_row();
+        let mut ways = vec![0u128; next.len()];
+        for &g in self.groups.iter().rev() {
+            self.fill_row(g, &next, &mut ways);
+            std::mem::swap(&mut ways, &mut next);
+        }
+        next[0]
+    }
+
+    /// Every row of the DP in `possibilities`, so `table[g][i]` is the number
+    /// of ways to place groups `g..` in springs `i..`
+    fn table(&self) -> Vec<Vec<u128>> {
+        let mut table = vec![self.last_row()];
+        for &g in self.groups.iter().rev() {
+            let mut ways = vec![0u128; table[0].len()];
+            self.fill_row(g, table.last().unwrap(), &mut ways);
+            table.push(ways);
+        }
+        table.reverse();
+        table
+    }
+
+    #[must_use]
+    pub fn springs(&self) -> &[Spring] {
+        &self.springs
+    }
 
-            if matches!(head, Spring::Unknown | Spring::Damaged) {
-                let g = self.groups[0];
-                let n_springs = self
-                    .springs
+    /// The `k`-th arrangement, in lexicographic order of the printed record
+    /// (so `#` comes before `.`)
+    #[must_use]
+    pub fn nth_arrangement(&self, k: u128) -> Option<Record> {
+        self.nth_in(&self.table(), k)
+    }
+
+    fn nth_in(&self, table: &[Vec<u128>], mut k: u128) -> Option<Record> {
+        if k >= table[0][0] {
+            return None;
+        }
+        let n = self.springs.len();
+        let mut springs = self.springs.clone();
+        let (mut i, mut g) = (0, 0);
+        while i < n {
+            if let Some(&len) = self.groups.get(g) {
+                let run = self.springs[i..]
                     .iter()
-                    .take(g)
-                    .all(|s| s != &Spring::Operational);
-                let last_oper = self.springs.get(g);
-                let last_oper = if let Some(o) = last_oper {
-                    o != &Spring::Damaged
-                } else {
-                    true
-                };
-                if g <= self.springs.len() && n_springs && last_oper {
-                    // We are allowed to consume the next group
-                    let mut clone = self.clone();
-                    // Add one to consume the separator
-                    clone.springs = clone
-                        .springs
-                        .into_iter()
-                        .skip(clone.groups[0] + 1)
-                        .collect();
-                    clone.groups = clone.groups.into_iter().skip(1).collect();
-                    result += clone.possibilities();
+                    .take_while(|&&s| s != Spring::Operational)
+                    .count();
+                if self.fits(i, len, run) {
+                    let here = table[g + 1][(i + len + 1).min(n)];
+                    if k < here {
+                        springs[i..i + len].fill(Spring::Damaged);
+                        if let Some(s) = springs.get_mut(i + len) {
+                            *s = Spring::Operational;
+                        }
+                        i += len + 1;
+                        g += 1;
+                        continue;
+                    }
+                    k -= here;
                 }
             }
-            result
-        }
-    }
-
-    pub fn memoized_possibilities(&self, cache: &mut HashMap<Record, usize>) -> usize {
-        if let Some(ans) = cache.get(self) {
-            return *ans;
-        }
-        if self.springs.is_empty() {
-            usize::from(self.groups.is_empty())
-        } else if self.groups.is_empty() {
-            usize::from(!self.springs.contains(&Spring::Damaged))
-        } else {
-            // At this point, we need to head to the base case.
-            let mut result = 0;
-            let head = self.springs.first().unwrap();
-            if matches!(head, Spring::Unknown | Spring::Operational) {
-                // If I'm operational, (or one side of the don't know), then no group is affected.
-                // So in this case it's just the possibilities of everything to the right
-                let mut clone = self.clone();
-                clone.springs = clone.springs.into_iter().skip(1).collect();
-                result += clone.memoized_possibilities(cache);
+            springs[i] = Spring::Operational;
+            i += 1;
+        }
+        Some(Record {
+            springs,
+            groups: self.groups.clone(),
+        })
+    }
+
+    /// Lazily walks every arrangement, in the same order as `nth_arrangement`
+    pub fn arrangements(&self) -> impl Iterator<Item = Record> + '_ {
+        let table = self.table();
+        (0..table[0][0]).map(move |k| self.nth_in(&table, k).unwrap())
+    }
+
+    /// Picks one arrangement uniformly at random, or `None` if there aren't any
+    pub fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Option<Record> {
+        let table = self.table();
+        let total = table[0][0];
+        (total > 0).then(|| self.nth_in(&table, rng.gen_range(0..total)).unwrap())
+    }
+
+    /// Repeats the record `n` times, with `separator` between each copy of the
+    /// springs
+    #[must_use]
+    pub fn unfold(&self, n: usize, separator: Spring) -> Record {
+        let mut springs = Vec::with_capacity(n * (self.springs.len() + 1));
+        for i in 0..n {
+            if i > 0 {
+                springs.push(separator);
             }
+            springs.extend(&self.springs);
+        }
+        Record {
+            springs,
+            groups: self.groups.repeat(n),
+        }
+    }
 
-            if matches!(head, Spring::Unknown | Spring::Damaged) {
-                let g = self.groups[0];
-                let n_springs = self
-                    .springs
-                    .iter()
-                    .take(g)
-                    .all(|s| s != &Spring::Operational);
-                let last_oper = self.springs.get(g);
-                let last_oper = if let Some(o) = last_oper {
-                    o != &Spring::Damaged
+    /// For every `?`, how many arrangements have it damaged. Returns `None` if
+    /// there are no arrangements at all.
+    ///
+    /// The count for spring `i` is the sum, over every placement of a group
+    /// `g` covering `i`, of the ways to place everything before it times the
+    /// ways to place everything after it. The "after" half is just `table`;
+    /// `before[g][s]` is the number of ways to fill springs `..s` with groups
+    /// `..g`, where spring `s - 1` isn't damaged.
+    #[must_use]
+    pub fn analyze(&self) -> Option<Vec<Cell>> {
+        let n = self.springs.len();
+        let after = self.table();
+        let total = after[0][0];
+        if total == 0 {
+            return None;
+        }
+        let mut runs = vec![0; n + 1];
+        for i in (0..n).rev() {
+            if self.springs[i] != Spring::Operational {
+                runs[i] = runs[i + 1] + 1;
+            }
+        }
+
+        let mut before = vec![vec![0u128; n + 1]; self.groups.len() + 1];
+        before[0][0] = 1;
+        for g in 0..=self.groups.len() {
+            for s in 1..=n {
+                let mut total = 0;
+                if self.springs[s - 1] != Spring::Damaged {
+                    total += before[g][s - 1];
+                }
+                // Spring s - 1 could be the separator after group g - 1
+                if g > 0 && s > self.groups[g - 1] {
+                    let t = s - 1 - self.groups[g - 1];
+                    if self.fits(t, self.groups[g - 1], runs[t]) {
+                        total += before[g - 1][t];
+                    }
+                }
+                before[g][s] = total;
+            }
+        }
+
+        // Add each placement's count to the whole span it covers at once
+        let mut deltas = vec![0u128; n + 1];
+        for (g, &len) in self.groups.iter().enumerate() {
+            for s in 0..n {
+                if self.fits(s, len, runs[s]) {
+                    let ways = before[g][s] * after[g + 1][(s + len + 1).min(n)];
+                    // The running sum never goes negative, so wrapping is fine
+                    deltas[s] = deltas[s].wrapping_add(ways);
+                    deltas[s + len] = deltas[s + len].wrapping_sub(ways);
+                }
+            }
+        }
+
+        let mut damaged = 0;
+        let mut cells = vec![];
+        for (i, &spring) in self.springs.iter().enumerate() {
+            damaged = deltas[i].wrapping_add(damaged);
+            if spring == Spring::Unknown {
+                let certainty = if damaged == total {
+                    Certainty::Damaged
+                } else if damaged == 0 {
+                    Certainty::Operational
                 } else {
-                    true
+                    Certainty::Ambiguous
                 };
-                if g <= self.springs.len() && n_springs && last_oper {
-                    // We are allowed to consume the next group
-                    let mut clone = self.clone();
-                    // Add one to consume the separator
-                    clone.springs = clone
-                        .springs
-                        .into_iter()
-                        .skip(clone.groups[0] + 1)
-                        .collect();
-                    clone.groups = clone.groups.into_iter().skip(1).collect();
-                    result += clone.memoized_possibilities(cache);
-                }
+                cells.push(Cell {
+                    index: i,
+                    certainty,
+                    damaged,
+                });
             }
-            cache.insert(self.clone(), result);
-            result
         }
+        Some(cells)
+    }
+
+    /// Fills in every `?` that's the same in all arrangements, like solving a
+    /// row of a nonogram
+    #[must_use]
+    pub fn solve(&self) -> Option<Record> {
+        let mut solved = self.clone();
+        for cell in self.analyze()? {
+            solved.springs[cell.index] = match cell.certainty {
+                Certainty::Damaged => Spring::Damaged,
+                Certainty::Operational => Spring::Operational,
+                Certainty::Ambiguous => Spring::Unknown,
+            };
+        }
+        Some(solved)
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Certainty {
+    Damaged,
+    Operational,
+    Ambiguous,
+}
+
+/// What we know about a single `?` across every arrangement
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Cell {
+    pub index: usize,
+    pub certainty: Certainty,
+    /// How many arrangements have this spring damaged
+    pub damaged: u128,
+}
+
 impl FromStr for Record {
     type Err = ();
     fn from_str(s: &str) -> Result<Self, Self::Err> {
@@ -178,23 +345,122 @@ pub fn gen(input: &str) -> Vec<Record> {
 }
 
 #[aoc(day12, part1)]
-pub fn part1(records: &[Record]) -> usize {
+pub fn part1(records: &[Record]) -> u128 {
     records.iter().map(Record::possibilities).sum()
 }
 
 #[aoc(day12, part2)]
-pub fn part2(records: &[Record]) -> usize {
+pub fn part2(records: &[Record]) -> u128 {
     records
         .par_iter()
-        .cloned()
-        .map(|mut r: Record| {
-            let springs = r.springs.clone();
-            for _ in 0..4 {
-                r.springs.push(Spring::Unknown);
-                r.springs.extend(&springs);
-            }
-            r.groups = r.groups.repeat(5);
-            r.memoized_possibilities(&mut HashMap::new())
-        })
+        .map(|r| r.unfold(5, Spring::Unknown).possibilities())
         .sum()
 }
+
+#[cfg(test)]
+mod test {
+    use super::{Certainty, Record, Spring};
+    use rand::{rngs::StdRng, SeedableRng};
+
+    const EXAMPLE: &str = "???.### 1,1,3
+.??..??...?##. 1,1,3
+?#?#?#?#?#?#?#? 1,3,1,6
+????.#...#... 4,1,1
+????.######..#####. 1,6,5
+?###???????? 3,2,1";
+
+    /// Every way to fill in the `?`s that matches the groups, sorted
+    fn brute_force(record: &Record) -> Vec<String> {
+        let unknowns: Vec<_> = (0..record.springs.len())
+            .filter(|&i| record.springs[i] == Spring::Unknown)
+            .collect();
+        let mut found = vec![];
+        for mask in 0u32..1 << unknowns.len() {
+            let mut springs = record.springs.clone();
+            for (bit, &i) in unknowns.iter().enumerate() {
+                springs[i] = if mask & (1 << bit) != 0 {
+                    Spring::Damaged
+                } else {
+                    Spring::Operational
+                };
+            }
+            let groups: Vec<usize> = springs
+                .split(|&s| s == Spring::Operational)
+                .map(<[Spring]>::len)
+                .filter(|&len| len > 0)
+                .collect();
+            if groups == record.groups {
+                found.push(
+                    Record {
+                        springs,
+                        groups: groups.clone(),
+                    }
+                    .to_string(),
+                );
+            }
+        }
+        found.sort();
+        found
+    }
+
+    #[test]
+    pub fn nth_arrangement() {
+        for line in EXAMPLE.lines() {
+            let record: Record = line.parse().unwrap();
+            let expected = brute_force(&record);
+            let count = record.possibilities();
+            assert_eq!(count, expected.len() as u128);
+            let indexed: Vec<_> = (0..count)
+                .map(|k| record.nth_arrangement(k).unwrap().to_string())
+                .collect();
+            assert_eq!(indexed, expected);
+            assert_eq!(record.nth_arrangement(count), None);
+            let walked: Vec<_> = record.arrangements().map(|r| r.to_string()).collect();
+            assert_eq!(walked, expected);
+        }
+    }
+
+    #[test]
+    pub fn sample() {
+        let mut rng = StdRng::seed_from_u64(12);
+        for line in EXAMPLE.lines() {
+            let record: Record = line.parse().unwrap();
+            let expected = brute_force(&record);
+            for _ in 0..20 {
+                let sampled = record.sample(&mut rng).unwrap().to_string();
+                assert!(expected.contains(&sampled));
+            }
+        }
+        let impossible: Record = "#.# 3".parse().unwrap();
+        assert_eq!(impossible.sample(&mut rng), None);
+    }
+
+    #[test]
+    pub fn analyze() {
+        for line in EXAMPLE.lines() {
+            let record: Record = line.parse().unwrap();
+            let arrangements: Vec<_> = record.arrangements().collect();
+            let total = arrangements.len() as u128;
+            let cells = record.analyze().unwrap();
+            let unknowns = record.springs.iter().filter(|&&s| s == Spring::Unknown);
+            assert_eq!(cells.len(), unknowns.count());
+            for cell in cells {
+                let damaged = arrangements
+                    .iter()
+                    .filter(|r| r.springs[cell.index] == Spring::Damaged)
+                    .count() as u128;
+                assert_eq!(cell.damaged, damaged, "{line} at {}", cell.index);
+                let certainty = match damaged {
+                    0 => Certainty::Operational,
+                    d if d == total => Certainty::Damaged,
+                    _ => Certainty::Ambiguous,
+                };
+                assert_eq!(cell.certainty, certainty);
+            }
+        }
+        let impossible: Record = "#.# 3".parse().unwrap();
+        assert_eq!(impossible.analyze(), None);
+        let solved: Record = "?###???????? 3,2,1".parse().unwrap();
+        assert_eq!(solved.solve().unwrap().to_string(), ".###.??????? 3,2,1");
+    }
+}
@@ -1,97 +1,216 @@
 use aoc_runner_derive::aoc;
+use std::str::FromStr;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum Reflection {
+/// A line of reflection, and how many lines on each side it reaches.
+/// `Horizontal(r, _)` sits between rows `r - 1` and `r`, and `Vertical(c, _)`
+/// between columns `c - 1` and `c`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Reflection {
     Horizontal(usize, usize),
     Vertical(usize, usize),
 }
 
-fn find_reflection(elements: &[&str]) -> Option<(usize, usize)> {
-    for mid in 1..elements.len() {
-        // Work our way out. As long as they match, we should be good
-        let (left, right) = elements.split_at(mid);
-        let result = left.iter().rev().zip(right.iter()).all(|(l, r)| l == r);
-        if result {
-            return Some((mid, left.len().min(right.len())));
+impl Reflection {
+    #[must_use]
+    pub fn summary(self) -> usize {
+        match self {
+            Reflection::Horizontal(r, _) => r * 100,
+            Reflection::Vertical(c, _) => c,
+        }
+    }
+}
+
+/// A pattern with each row and each column packed into a bitmask, where a set
+/// bit is a rock (`#`). Patterns can be at most 64 cells on each side.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Pattern {
+    rows: Vec<u64>,
+    cols: Vec<u64>,
+}
+
+impl FromStr for Pattern {
+    type Err = ();
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let grid: Vec<&[u8]> = s.lines().map(str::as_bytes).collect();
+        let width = grid.first().ok_or(())?.len();
+        if width > 64 || grid.len() > 64 || grid.iter().any(|row| row.len() != width) {
+            return Err(());
+        }
+        let mut rows = vec![0u64; grid.len()];
+        let mut cols = vec![0u64; width];
+        for (r, row) in grid.iter().enumerate() {
+            for (c, &ch) in row.iter().enumerate() {
+                match ch {
+                    b'#' => {
+                        rows[r] |= 1 << c;
+                        cols[c] |= 1 << r;
+                    }
+                    b'.' => {}
+                    _ => return Err(()),
+                }
+            }
         }
+        Ok(Pattern { rows, cols })
     }
-    None
 }
 
-fn find_all_reflections<'a>(elements: &'a [&'a str]) -> impl Iterator<Item = (usize, usize)> + 'a {
-    (1..elements.len()).filter_map(|mid| {
-        // Work our way out. As long as they match, we should be good
-        let (left, right) = elements.split_at(mid);
-        let result = left.iter().rev().zip(right.iter()).all(|(l, r)| l == r);
-        if result {
-            return Some((mid, left.len().min(right.len())));
+/// Finds every line of reflection where the mirrored halves differ in exactly
+/// `smudges` cells. Every pair of mirrored lines contributes the popcount of
+/// their XOR, and we bail as soon as we've seen too many.
+fn find_all_reflections(lines: &[u64], smudges: u32) -> impl Iterator<Item = (usize, usize)> + '_ {
+    (1..lines.len()).filter_map(move |mid| {
+        let (left, right) = lines.split_at(mid);
+        let mut mismatched = 0;
+        for (l, r) in left.iter().rev().zip(right) {
+            mismatched += (l ^ r).count_ones();
+            if mismatched > smudges {
+                return None;
+            }
         }
-        None
+        (mismatched == smudges).then_some((mid, left.len().min(right.len())))
     })
 }
 
-fn solve_puzzle(puzzle: &str) -> Option<Reflection> {
-    let rows: Vec<&str> = puzzle.lines().collect();
-    let mut cols = vec![String::new(); rows[0].len()];
-    for row in &rows {
-        for (c, ch) in row.chars().enumerate() {
-            cols[c].push(ch);
+fn find_reflection(lines: &[u64], smudges: u32) -> Option<(usize, usize)> {
+    find_all_reflections(lines, smudges).next()
+}
+
+/// A cell that has to flip for a reflection to hold. Flipping its mirror
+/// image instead works just as well.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Smudge {
+    pub cell: (usize, usize),
+    pub mirror: (usize, usize),
+}
+
+impl Pattern {
+    /// The reflection you'd see if exactly `smudges` cells were flipped
+    #[must_use]
+    pub fn reflection(&self, smudges: u32) -> Option<Reflection> {
+        if let Some(r) = find_reflection(&self.rows, smudges) {
+            Some(Reflection::Horizontal(r.0, r.1))
+        } else {
+            find_reflection(&self.cols, smudges).map(|c| Reflection::Vertical(c.0, c.1))
         }
     }
-    let cols: Vec<&str> = cols.iter().map(|x| &**x).collect();
 
-    if let Some(r) = find_reflection(&rows) {
-        Some(Reflection::Horizontal(r.0, r.1))
-    } else {
-        find_reflection(&cols).map(|c| Reflection::Vertical(c.0, c.1))
+    /// Every reflection, horizontal then vertical, that needs exactly
+    /// `smudges` cells flipped
+    #[must_use]
+    pub fn reflections(&self, smudges: u32) -> Vec<Reflection> {
+        find_all_reflections(&self.rows, smudges)
+            .map(|(r, span)| Reflection::Horizontal(r, span))
+            .chain(
+                find_all_reflections(&self.cols, smudges)
+                    .map(|(c, span)| Reflection::Vertical(c, span)),
+            )
+            .collect()
+    }
+
+    /// The cells on the top (or left) side of `reflection` that don't match
+    /// their mirror image
+    #[must_use]
+    pub fn smudges(&self, reflection: Reflection) -> Vec<Smudge> {
+        let (lines, mid, span) = match reflection {
+            Reflection::Horizontal(mid, span) => (&self.rows, mid, span),
+            Reflection::Vertical(mid, span) => (&self.cols, mid, span),
+        };
+        let mut smudges = vec![];
+        for offset in 0..span {
+            let (near, far) = (mid - 1 - offset, mid + offset);
+            let mut diff = lines[near] ^ lines[far];
+            while diff != 0 {
+                let along = diff.trailing_zeros() as usize;
+                diff &= diff - 1;
+                smudges.push(match reflection {
+                    Reflection::Horizontal(..) => Smudge {
+                        cell: (near, along),
+                        mirror: (far, along),
+                    },
+                    Reflection::Vertical(..) => Smudge {
+                        cell: (along, near),
+                        mirror: (along, far),
+                    },
+                });
+            }
+        }
+        smudges
+    }
+
+    fn is_rock(&self, (r, c): (usize, usize)) -> bool {
+        self.rows[r] & (1 << c) != 0
+    }
+
+    /// Draws the pattern with `reflection` on it: a row of `-` or a column of
+    /// `|` along the mirror, and any smudges as `*`
+    #[must_use]
+    pub fn view(&self, reflection: Reflection) -> PatternView<'_> {
+        PatternView {
+            pattern: self,
+            reflection,
+        }
     }
 }
 
-fn solve_puzzle_again(puzzle: &str, prev: &Reflection) -> Option<Reflection> {
-    let rows: Vec<&str> = puzzle.lines().collect();
-    let mut cols = vec![String::new(); rows[0].len()];
-    for row in &rows {
-        for (c, ch) in row.chars().enumerate() {
-            cols[c].push(ch);
+impl std::fmt::Display for Pattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for r in 0..self.rows.len() {
+            for c in 0..self.cols.len() {
+                f.write_str(if self.is_rock((r, c)) { "#" } else { "." })?;
+            }
+            writeln!(f)?;
         }
+        Ok(())
     }
-    let cols: Vec<&str> = cols.iter().map(|x| &**x).collect();
-    // println!("{:?}", rows);
-    // println!("{:?}", cols);
-    let out = find_all_reflections(&rows)
-        .map(|r| Reflection::Horizontal(r.0, r.1))
-        .chain(find_all_reflections(&cols).map(|c| Reflection::Vertical(c.0, c.1)))
-        .find(|r| r != prev);
-    out
 }
 
-fn try_smudges(puzzle: &str) -> Reflection {
-    // Get the original
-    let orig = solve_puzzle(puzzle).unwrap();
-    let out = puzzle.char_indices().find_map(|(c, ch)| {
-        let mut changed = String::from(puzzle);
-        if ch == '.' {
-            changed.replace_range(c..=c, "#");
-        } else if ch == '#' {
-            changed.replace_range(c..=c, ".");
-        } else {
-            return None;
+#[derive(Debug, Clone, Copy)]
+pub struct PatternView<'a> {
+    pattern: &'a Pattern,
+    reflection: Reflection,
+}
+
+impl std::fmt::Display for PatternView<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let smudges: Vec<_> = self
+            .pattern
+            .smudges(self.reflection)
+            .into_iter()
+            .map(|s| s.cell)
+            .collect();
+        let (mirror_row, mirror_col) = match self.reflection {
+            Reflection::Horizontal(r, _) => (Some(r), None),
+            Reflection::Vertical(c, _) => (None, Some(c)),
+        };
+        let width = self.pattern.cols.len();
+        for r in 0..self.pattern.rows.len() {
+            if mirror_row == Some(r) {
+                writeln!(f, "{}", "-".repeat(width))?;
+            }
+            for c in 0..width {
+                if mirror_col == Some(c) {
+                    f.write_str("|")?;
+                }
+                if smudges.contains(&(r, c)) {
+                    f.write_str("*")?;
+                } else if self.pattern.is_rock((r, c)) {
+                    f.write_str("#")?;
+                } else {
+                    f.write_str(".")?;
+                }
+            }
+            writeln!(f)?;
         }
-        solve_puzzle_again(&changed, &orig)
-    });
-    out.unwrap()
+        Ok(())
+    }
 }
 
 #[aoc(day13, part1)]
 pub fn part1(input: &str) -> usize {
     input
         .split("\n\n")
-        .map(solve_puzzle)
-        .map(Option::unwrap)
-        .map(|r| match r {
-            Reflection::Horizontal(r, _) => r * 100,
-            Reflection::Vertical(c, _) => c,
-        })
+        .map(|p| p.parse::<Pattern>().unwrap())
+        .map(|p| p.reflection(0).unwrap().summary())
         .sum()
 }
 
@@ -99,10 +218,112 @@ pub fn part1(input: &str) -> usize {
 pub fn part2(input: &str) -> usize {
     input
         .split("\n\n")
-        .map(try_smudges)
-        .map(|r| match r {
-            Reflection::Horizontal(r, _) => r * 100,
-            Reflection::Vertical(c, _) => c,
-        })
+        .map(|p| p.parse::<Pattern>().unwrap())
+        .map(|p| p.reflection(1).unwrap().summary())
         .sum()
 }
+
+#[cfg(test)]
+mod test {
+    use super::{part1, part2, Pattern, Reflection, Smudge};
+
+    const FIRST: &str = "#.##..##.
+..#.##.#.
+##......#
+##......#
+..#.##.#.
+..##..##.
+#.#.##.#.";
+
+    const SECOND: &str = "#...##..#
+#....#..#
+..##..###
+#####.##.
+#####.##.
+..##..###
+#....#..#";
+
+    #[test]
+    pub fn examples() {
+        let input = format!("{FIRST}\n\n{SECOND}");
+        assert_eq!(part1(&input), 405);
+        assert_eq!(part2(&input), 400);
+    }
+
+    #[test]
+    pub fn reflection() {
+        let first: Pattern = FIRST.parse().unwrap();
+        assert_eq!(first.reflection(0), Some(Reflection::Vertical(5, 4)));
+        assert_eq!(first.reflection(1), Some(Reflection::Horizontal(3, 3)));
+        let second: Pattern = SECOND.parse().unwrap();
+        assert_eq!(second.reflection(0), Some(Reflection::Horizontal(4, 3)));
+        assert_eq!(second.reflection(1), Some(Reflection::Horizontal(1, 1)));
+        assert_eq!("#.\n..".parse::<Pattern>().unwrap().reflection(0), None);
+        assert_eq!(first.to_string(), format!("{FIRST}\n"));
+    }
+
+    #[test]
+    pub fn limits() {
+        let square = vec!["#.".repeat(32); 64].join("\n");
+        assert!(square.parse::<Pattern>().is_ok());
+        let tall = vec!["#."; 65].join("\n");
+        assert!(tall.parse::<Pattern>().is_err());
+        let wide = "#.".repeat(33);
+        assert!(wide.parse::<Pattern>().is_err());
+        assert!("#.\n#".parse::<Pattern>().is_err());
+        assert!("#?".parse::<Pattern>().is_err());
+        assert!("".parse::<Pattern>().is_err());
+    }
+
+    #[test]
+    pub fn all_reflections() {
+        let first: Pattern = FIRST.parse().unwrap();
+        assert_eq!(first.reflections(0), vec![Reflection::Vertical(5, 4)]);
+        let second: Pattern = SECOND.parse().unwrap();
+        assert_eq!(second.reflections(1), vec![Reflection::Horizontal(1, 1)]);
+        // A blank pattern reflects everywhere
+        let blank: Pattern = "...\n...".parse().unwrap();
+        assert_eq!(
+            blank.reflections(0),
+            vec![
+                Reflection::Horizontal(1, 1),
+                Reflection::Vertical(1, 1),
+                Reflection::Vertical(2, 1),
+            ]
+        );
+    }
+
+    #[test]
+    pub fn smudges() {
+        let first: Pattern = FIRST.parse().unwrap();
+        assert_eq!(first.smudges(Reflection::Vertical(5, 4)), vec![]);
+        assert_eq!(
+            first.smudges(Reflection::Horizontal(3, 3)),
+            vec![Smudge {
+                cell: (0, 0),
+                mirror: (5, 0),
+            }]
+        );
+        let second: Pattern = SECOND.parse().unwrap();
+        assert_eq!(
+            second.smudges(Reflection::Horizontal(1, 1)),
+            vec![Smudge {
+                cell: (0, 4),
+                mirror: (1, 4),
+            }]
+        );
+    }
+
+    #[test]
+    pub fn view() {
+        let first: Pattern = FIRST.parse().unwrap();
+        let vertical = first.view(Reflection::Vertical(5, 4)).to_string();
+        assert_eq!(vertical.lines().next(), Some("#.##.|.##."));
+        assert!(vertical.lines().all(|l| l.chars().nth(5) == Some('|')));
+        let horizontal = first.view(Reflection::Horizontal(3, 3)).to_string();
+        let lines: Vec<_> = horizontal.lines().collect();
+        assert_eq!(lines.len(), 8);
+        assert_eq!(lines[0], "*.##..##.");
+        assert_eq!(lines[3], "---------");
+    }
+}
@@ -1,5 +1,7 @@
 use aoc_runner_derive::aoc;
-use std::{collections::HashMap, fmt::Display, str::FromStr};
+use std::{fmt::Display, hash::Hash, rc::Rc, str::FromStr};
+
+use crate::cycle;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 enum Space {
@@ -33,77 +35,246 @@ impl Display for Space {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
-struct Grid {
-    grid: Vec<Vec<Space>>,
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction {
+    North,
+    West,
+    South,
+    East,
+}
+
+/// The bits of word `w` that fall in the cell range `lo..hi`
+fn word_mask(w: usize, lo: usize, hi: usize) -> u64 {
+    let (first, last) = (w * 64, w * 64 + 64);
+    let (lo, hi) = (lo.max(first) - first, hi.min(last) - first);
+    if lo >= hi {
+        0
+    } else if hi - lo == 64 {
+        u64::MAX
+    } else {
+        ((1 << (hi - lo)) - 1) << lo
+    }
+}
+
+/// How many bits of `line` are set in `lo..hi`
+fn count_range(line: &[u64], lo: usize, hi: usize) -> u32 {
+    (lo / 64..hi.div_ceil(64))
+        .map(|w| (line[w] & word_mask(w, lo, hi)).count_ones())
+        .sum()
+}
+
+/// Sets the bits of `line` in `lo..hi` to `on`
+fn set_range(line: &mut [u64], lo: usize, hi: usize, on: bool) {
+    let first = lo / 64;
+    for (w, word) in line[first..hi.div_ceil(64)].iter_mut().enumerate() {
+        let mask = word_mask(first + w, lo, hi);
+        if on {
+            *word |= mask;
+        } else {
+            *word &= !mask;
+        }
+    }
+}
+
+/// Transposes a 64 by 64 bit matrix in place, where bit `c` of `a[r]` is the
+/// cell at `(r, c)`, by swapping ever smaller blocks
+fn transpose64(a: &mut [u64; 64]) {
+    let mut j = 32;
+    let mut m: u64 = 0x0000_0000_FFFF_FFFF;
+    while j != 0 {
+        let mut k = 0;
+        while k < 64 {
+            let t = ((a[k] >> j) ^ a[k + j]) & m;
+            a[k] ^= t << j;
+            a[k + j] ^= t;
+            k = (k + j + 1) & !j;
+        }
+        j >>= 1;
+        m ^= m << j;
+    }
+}
+
+/// Turns `rows` lines of `cols` bits into `cols` lines of `rows` bits, a 64
+/// by 64 block at a time
+fn transpose(bits: &[u64], rows: usize, cols: usize) -> Vec<u64> {
+    let (words, out_words) = (cols.div_ceil(64), rows.div_ceil(64));
+    let mut out = vec![0u64; cols * out_words];
+    let mut block = [0u64; 64];
+    for br in 0..out_words {
+        for bc in 0..words {
+            for (i, word) in block.iter_mut().enumerate() {
+                let r = br * 64 + i;
+                *word = if r < rows { bits[r * words + bc] } else { 0 };
+            }
+            transpose64(&mut block);
+            for (j, &word) in block.iter().enumerate() {
+                let c = bc * 64 + j;
+                if c < cols {
+                    out[c * out_words + br] = word;
+                }
+            }
+        }
+    }
+    out
+}
+
+/// One bitset per line (rows, or columns), and the runs of open cells
+/// between cube rocks (or walls) along each
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Lines {
+    /// 64-bit words per line
+    words: usize,
+    /// `lo..hi` for each segment of each line
+    segments: Vec<Vec<(usize, usize)>>,
+}
+
+impl Lines {
+    fn new(cubes: &[u64], lines: usize, len: usize) -> Self {
+        let words = len.div_ceil(64);
+        let segments = (0..lines)
+            .map(|l| {
+                let line = &cubes[l * words..(l + 1) * words];
+                let mut segments = vec![];
+                let mut lo = 0;
+                for i in 0..=len {
+                    if i == len || line[i / 64] & (1 << (i % 64)) != 0 {
+                        if lo < i {
+                            segments.push((lo, i));
+                        }
+                        lo = i + 1;
+                    }
+                }
+                segments
+            })
+            .collect();
+        Lines { words, segments }
+    }
+
+    /// Packs the round rocks in each segment against its low end (or its
+    /// high end): a popcount over the segment's words, then a mask
+    fn tilt(&self, round: &mut [u64], toward_low: bool) {
+        for (line, segments) in round.chunks_mut(self.words).zip(&self.segments) {
+            for &(lo, hi) in segments {
+                let count = count_range(line, lo, hi) as usize;
+                set_range(line, lo, hi, false);
+                if toward_low {
+                    set_range(line, lo, lo + count, true);
+                } else {
+                    set_range(line, hi - count, hi, true);
+                }
+            }
+        }
+    }
+}
+
+/// Everything about a platform that tilting doesn't change. It's shared
+/// between clones, so remembering a platform only costs its round rocks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Layout {
+    rows: usize,
+    cols: usize,
+    /// The cube rocks, a bitset per row
+    cubes: Vec<u64>,
+    by_row: Lines,
+    by_col: Lines,
+}
+
+/// A platform with the round rocks in a bitset per row, and the cube rocks in
+/// the shared layout. Tilting west or east works on the rows directly; north
+/// or south transposes them into columns first, a 64 by 64 block at a time.
+/// Either way, each segment between cube rocks gets a popcount and a refill,
+/// so a tilt costs a few word operations per segment and per 64 cells rather
+/// than anything per rock.
+#[derive(Debug, Clone)]
+pub struct Platform {
+    layout: Rc<Layout>,
+    round: Vec<u64>,
+}
+
+impl PartialEq for Platform {
+    fn eq(&self, other: &Self) -> bool {
+        self.round == other.round
+            && (Rc::ptr_eq(&self.layout, &other.layout) || self.layout == other.layout)
+    }
+}
+
+impl Eq for Platform {}
+
+impl Hash for Platform {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.round.hash(state);
+    }
 }
 
-impl FromStr for Grid {
+impl FromStr for Platform {
     type Err = ();
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let grid = s
+        let grid: Vec<Vec<Space>> = s
             .lines()
             .map(|r| r.chars().map(std::convert::Into::into).collect())
             .collect();
-        Ok(Grid { grid })
-    }
-}
-
-impl Grid {
-    fn transpose(&mut self) {
-        self.grid = if let Some(head) = self.grid.first() {
-            let mut out = vec![vec![Space::Empty; self.grid.len()]; head.len()];
-            for r in 0..self.grid.len() {
-                for (c, item) in out.iter_mut().enumerate().take(self.grid[r].len()) {
-                    item[r] = self.grid[r][c];
+        let rows = grid.len();
+        let cols = grid.first().map_or(0, Vec::len);
+        if grid.iter().any(|row| row.len() != cols) {
+            return Err(());
+        }
+        let words = cols.div_ceil(64);
+        let mut cubes = vec![0u64; rows * words];
+        let mut round = vec![0u64; rows * words];
+        for (r, row) in grid.iter().enumerate() {
+            for (c, &space) in row.iter().enumerate() {
+                let bit = 1 << (c % 64);
+                match space {
+                    Space::Cube => cubes[r * words + c / 64] |= bit,
+                    Space::Sphere => round[r * words + c / 64] |= bit,
+                    Space::Empty => {}
                 }
             }
-            out
-        } else {
-            vec![]
+        }
+        let layout = Layout {
+            rows,
+            cols,
+            by_row: Lines::new(&cubes, rows, cols),
+            by_col: Lines::new(&transpose(&cubes, rows, cols), cols, rows),
+            cubes,
         };
+        Ok(Platform {
+            layout: Rc::new(layout),
+            round,
+        })
     }
+}
 
-    pub fn tilt_north(&mut self) {
-        self.transpose();
-        for c in 0..self.grid.len() {
-            let col = &mut self.grid[c];
-            for r in 0..col.len() {
-                if col[r] == Space::Sphere {
-                    // It could roll. Find the first entry above me that
-                    // isn't occupied
-                    let idx = col.iter().take(r).enumerate().rev().find_map(|(i, s)| {
-                        if s == &Space::Empty {
-                            None
-                        } else {
-                            Some(i)
-                        }
-                    });
-                    // Clear mine, but then replace with idx
-                    let idx = if let Some(idx) = idx { idx + 1 } else { 0 };
-                    col[r] = Space::Empty;
-                    col[idx] = Space::Sphere;
-                }
+impl Platform {
+    fn is_set(bits: &[u64], words: usize, r: usize, c: usize) -> bool {
+        bits[r * words + c / 64] & (1 << (c % 64)) != 0
+    }
+
+    pub fn tilt(&mut self, dir: Direction) {
+        let layout = &*self.layout;
+        let (rows, cols) = (layout.rows, layout.cols);
+        match dir {
+            Direction::West => layout.by_row.tilt(&mut self.round, true),
+            Direction::East => layout.by_row.tilt(&mut self.round, false),
+            Direction::North | Direction::South => {
+                let mut by_col = transpose(&self.round, rows, cols);
+                layout.by_col.tilt(&mut by_col, dir == Direction::North);
+                self.round = transpose(&by_col, cols, rows);
             }
         }
-        self.transpose();
+    }
+
+    pub fn tilt_north(&mut self) {
+        self.tilt(Direction::North);
     }
     pub fn tilt_south(&mut self) {
-        self.grid = self.grid.iter().cloned().rev().collect();
-        self.tilt_north();
-        self.grid = self.grid.iter().cloned().rev().collect();
+        self.tilt(Direction::South);
     }
     pub fn tilt_west(&mut self) {
-        self.transpose();
-        self.tilt_north();
-        self.transpose();
+        self.tilt(Direction::West);
     }
     pub fn tilt_east(&mut self) {
-        self.transpose();
-        self.grid = self.grid.iter().cloned().rev().collect();
-        self.tilt_north();
-        self.grid = self.grid.iter().cloned().rev().collect();
-        self.transpose();
+        self.tilt(Direction::East);
     }
     pub fn cycle(&mut self) {
         self.tilt_north();
@@ -111,21 +282,100 @@ impl Grid {
         self.tilt_south();
         self.tilt_east();
     }
+    /// The total load on the support beams along `wall`: each round rock
+    /// weighs as much as its distance from the opposite wall, counting its
+    /// own row or column
+    #[must_use]
+    pub fn load(&self, wall: Direction) -> usize {
+        let Layout { rows, cols, .. } = *self.layout;
+        // Count the rocks in each line parallel to the wall
+        let (lines, len) = match wall {
+            Direction::North | Direction::South => (self.round.clone(), rows),
+            Direction::West | Direction::East => (transpose(&self.round, rows, cols), cols),
+        };
+        let words = lines.len().checked_div(len).unwrap_or(0);
+        (0..len)
+            .map(|i| {
+                let count = lines[i * words..(i + 1) * words]
+                    .iter()
+                    .map(|w| w.count_ones() as usize)
+                    .sum::<usize>();
+                let weight = match wall {
+                    Direction::North | Direction::West => len - i,
+                    Direction::South | Direction::East => i + 1,
+                };
+                count * weight
+            })
+            .sum()
+    }
+
+    #[must_use]
     pub fn north_load(&self) -> usize {
-        self.grid
-            .iter()
-            .rev()
-            .enumerate()
-            .map(|(r, row)| row.iter().filter(|&&c| c == Space::Sphere).count() * (r + 1))
-            .sum::<usize>()
+        self.load(Direction::North)
+    }
+
+    /// The load on `wall` after every spin cycle, until the platform starts
+    /// repeating itself
+    #[must_use]
+    pub fn load_series(&self, wall: Direction) -> LoadSeries {
+        let history = cycle::hashed(self.clone(), Platform::cycle);
+        LoadSeries {
+            cycle: history.cycle,
+            loads: history.states().iter().map(|p| p.load(wall)).collect(),
+        }
     }
 }
 
-impl Display for Grid {
+/// The load on one wall over time. `loads[n]` is the load after `n` spin
+/// cycles, and once the platform repeats, so does the load.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoadSeries {
+    pub cycle: cycle::Cycle,
+    loads: Vec<usize>,
+}
+
+impl LoadSeries {
+    /// The load after `n` spin cycles
+    #[must_use]
+    pub fn at(&self, n: usize) -> usize {
+        self.loads[self.cycle.reduce(n)]
+    }
+
+    /// The loads before the platform settles into its cycle
+    #[must_use]
+    pub fn transient(&self) -> &[usize] {
+        &self.loads[..self.cycle.prefix]
+    }
+
+    /// The loads over one lap of the cycle
+    #[must_use]
+    pub fn periodic(&self) -> &[usize] {
+        &self.loads[self.cycle.prefix..]
+    }
+
+    /// The first spin cycle after which the load is exactly `load`. Every
+    /// state the platform can reach is in the series, so if it isn't here it
+    /// never happens.
+    #[must_use]
+    pub fn first(&self, load: usize) -> Option<usize> {
+        self.loads.iter().position(|&l| l == load)
+    }
+}
+
+impl Display for Platform {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        for row in &self.grid {
-            for col in row {
-                write!(f, "{col}")?;
+        let Layout { rows, cols, .. } = *self.layout;
+        let words = cols.div_ceil(64);
+        for r in 0..rows {
+            for c in 0..cols {
+                let space = if Self::is_set(&self.layout.cubes, words, r, c) {
+                    Space::Cube
+                } else if Self::is_set(&self.round, words, r, c) {
+                    Space::Sphere
+                } else {
+                    Space::Empty
+                };
+                write!(f, "{space}")?;
             }
             writeln!(f)?;
         }
@@ -135,27 +385,117 @@ impl Display for Grid {
 
 #[aoc(day14, part1)]
 pub fn part1(input: &str) -> usize {
-    let mut grid: Grid = input.parse().unwrap();
-    grid.tilt_north();
-    grid.north_load()
+    let mut platform: Platform = input.parse().unwrap();
+    platform.tilt_north();
+    platform.north_load()
 }
 
 #[aoc(day14, part2)]
 pub fn part2(input: &str) -> usize {
-    let mut grid: Grid = input.parse().unwrap();
-    let mut past = HashMap::new();
-
-    for i in 0..1_000_000_000 {
-        if let Some(idx) = past.get(&grid) {
-            let modulus = i - *idx;
-            let oper = 1_000_000_000 - *idx;
-            for _ in 0..(oper % modulus) {
-                grid.cycle();
+    let platform: Platform = input.parse().unwrap();
+    platform.load_series(Direction::North).at(1_000_000_000)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{part1, part2, Direction, Platform};
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    const EXAMPLE: &str = "O....#....
+O.OO#....#
+.....##...
+OO.#O....O
+.O.....O#.
+O.#..O.#.#
+..O..#O..O
+.......O..
+#....###..
+#OO..#....";
+
+    /// Rolls every round rock north one column at a time
+    fn naive_north(grid: &mut [Vec<u8>]) {
+        for col in 0..grid[0].len() {
+            let mut free = 0;
+            for row in 0..grid.len() {
+                match grid[row][col] {
+                    b'#' => free = row + 1,
+                    b'O' => {
+                        grid[row][col] = b'.';
+                        grid[free][col] = b'O';
+                        free += 1;
+                    }
+                    _ => {}
+                }
             }
-            break;
         }
-        past.insert(grid.clone(), i);
-        grid.cycle();
     }
-    grid.north_load()
+
+    fn rotate_clockwise(grid: &[Vec<u8>]) -> Vec<Vec<u8>> {
+        let rows = grid.len();
+        (0..grid[0].len())
+            .map(|c| (0..rows).map(|r| grid[rows - 1 - r][c]).collect())
+            .collect()
+    }
+
+    fn render(grid: &[Vec<u8>]) -> String {
+        grid.iter()
+            .map(|row| String::from_utf8(row.clone()).unwrap() + "\n")
+            .collect()
+    }
+
+    fn random_grid(rng: &mut StdRng, rows: usize, cols: usize) -> Vec<Vec<u8>> {
+        (0..rows)
+            .map(|_| {
+                (0..cols)
+                    .map(|_| match rng.gen_range(0..10) {
+                        0 => b'#',
+                        1..=3 => b'O',
+                        _ => b'.',
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    pub fn examples() {
+        assert_eq!(part1(EXAMPLE), 136);
+        assert_eq!(part2(EXAMPLE), 64);
+    }
+
+    #[test]
+    pub fn cycles_match_naive() {
+        let mut rng = StdRng::seed_from_u64(14);
+        for (rows, cols) in [(1, 1), (3, 200), (70, 130), (129, 65), (64, 64)] {
+            let mut grid = random_grid(&mut rng, rows, cols);
+            let mut platform: Platform = render(&grid).parse().unwrap();
+            for _ in 0..3 {
+                platform.cycle();
+                for _ in 0..4 {
+                    naive_north(&mut grid);
+                    grid = rotate_clockwise(&grid);
+                }
+                assert_eq!(platform.to_string(), render(&grid), "{rows}x{cols}");
+            }
+        }
+    }
+
+    #[test]
+    pub fn loads_match_naive() {
+        let mut rng = StdRng::seed_from_u64(41);
+        let (rows, cols) = (67, 131);
+        let grid = random_grid(&mut rng, rows, cols);
+        let platform: Platform = render(&grid).parse().unwrap();
+        let naive = |weight: &dyn Fn(usize, usize) -> usize| -> usize {
+            (0..rows)
+                .flat_map(|r| (0..cols).map(move |c| (r, c)))
+                .filter(|&(r, c)| grid[r][c] == b'O')
+                .map(|(r, c)| weight(r, c))
+                .sum()
+        };
+        assert_eq!(platform.load(Direction::North), naive(&|r, _| rows - r));
+        assert_eq!(platform.load(Direction::South), naive(&|r, _| r + 1));
+        assert_eq!(platform.load(Direction::West), naive(&|_, c| cols - c));
+        assert_eq!(platform.load(Direction::East), naive(&|_, c| c + 1));
+    }
 }
@@ -1,27 +1,26 @@
 use aoc_runner_derive::aoc;
-use std::str::FromStr;
+use std::{borrow::Borrow, fmt::Display, str::FromStr};
 
-fn digest(s: &str) -> usize {
-    let mut working = 0;
-    for c in s.as_bytes() {
-        let tmp = *c as usize;
-        working += tmp;
-        working *= 17;
-        working %= 256;
+/// The HASH algorithm from the puzzle
+#[must_use]
+pub fn digest(s: &[u8]) -> u8 {
+    let mut working: u8 = 0;
+    for &c in s {
+        working = working.wrapping_add(c).wrapping_mul(17);
     }
     working
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-enum Operation {
+pub enum Operation {
     Set(usize),
     Remove,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
-struct Instruction {
-    id: String,
-    op: Operation,
+pub struct Instruction {
+    pub id: String,
+    pub op: Operation,
 }
 
 impl FromStr for Instruction {
@@ -41,50 +40,559 @@ impl FromStr for Instruction {
     }
 }
 
-fn total_bucket(bucket: &[(String, usize)], b: usize) -> usize {
-    bucket
-        .iter()
-        .enumerate()
-        .map(|(i, inst)| (b + 1) * (i + 1) * inst.1)
-        .sum::<usize>()
+impl Display for Instruction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.op {
+            Operation::Set(len) => write!(f, "{}={len}", self.id),
+            Operation::Remove => write!(f, "{}-", self.id),
+        }
+    }
+}
+
+/// The HASHMAP from the puzzle: 256 boxes, each an ordered list of slots. A
+/// key's box is picked by an 8-bit hasher (`digest` unless you say otherwise),
+/// and within a box, keys stay in the order they were first inserted.
+#[derive(Clone)]
+pub struct HolidayHashMap<K, V> {
+    boxes: Vec<Vec<(K, V)>>,
+    hasher: fn(&[u8]) -> u8,
+    len: usize,
+}
+
+impl<K, V> Default for HolidayHashMap<K, V> {
+    fn default() -> Self {
+        Self::with_hasher(digest)
+    }
+}
+
+impl<K, V> HolidayHashMap<K, V> {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[must_use]
+    pub fn with_hasher(hasher: fn(&[u8]) -> u8) -> Self {
+        HolidayHashMap {
+            boxes: (0..256).map(|_| vec![]).collect(),
+            hasher,
+            len: 0,
+        }
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Every entry, box by box and slot by slot
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.boxes.iter().flatten().map(|(k, v)| (k, v))
+    }
+
+    /// Every entry with the box and slot it's in
+    pub fn slots(&self) -> impl Iterator<Item = (usize, usize, &K, &V)> {
+        self.boxes.iter().enumerate().flat_map(|(b, bucket)| {
+            bucket
+                .iter()
+                .enumerate()
+                .map(move |(s, (k, v))| (b, s, k, v))
+        })
+    }
+
+    /// The contents of a single box, in slot order
+    #[must_use]
+    pub fn bucket(&self, b: u8) -> &[(K, V)] {
+        &self.boxes[usize::from(b)]
+    }
+}
+
+impl<K: AsRef<[u8]> + Eq, V> HolidayHashMap<K, V> {
+    #[must_use]
+    pub fn box_of<Q: AsRef<[u8]> + ?Sized>(&self, key: &Q) -> u8 {
+        (self.hasher)(key.as_ref())
+    }
+
+    fn find<Q>(&self, key: &Q) -> (usize, Option<usize>)
+    where
+        K: Borrow<Q>,
+        Q: AsRef<[u8]> + Eq + ?Sized,
+    {
+        let b = usize::from(self.box_of(key));
+        (b, self.boxes[b].iter().position(|(k, _)| k.borrow() == key))
+    }
+
+    /// Puts `value` in the slot for `key`, returning what was there. A new key
+    /// goes at the back of its box; an existing one keeps its slot.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        match self.entry(key) {
+            Entry::Occupied(mut e) => Some(std::mem::replace(e.get_mut(), value)),
+            Entry::Vacant(e) => {
+                e.insert(value);
+                None
+            }
+        }
+    }
+
+    /// Takes `key` out of its box, shifting everything behind it forward
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: AsRef<[u8]> + Eq + ?Sized,
+    {
+        let (b, slot) = self.find(key);
+        let (_, v) = self.boxes[b].remove(slot?);
+        self.len -= 1;
+        Some(v)
+    }
+
+    #[must_use]
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: AsRef<[u8]> + Eq + ?Sized,
+    {
+        let (b, slot) = self.find(key);
+        slot.map(|s| &self.boxes[b][s].1)
+    }
+
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: AsRef<[u8]> + Eq + ?Sized,
+    {
+        let (b, slot) = self.find(key);
+        slot.map(|s| &mut self.boxes[b][s].1)
+    }
+
+    #[must_use]
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: AsRef<[u8]> + Eq + ?Sized,
+    {
+        self.find(key).1.is_some()
+    }
+
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V> {
+        let (b, slot) = self.find(&key);
+        let bucket = &mut self.boxes[b];
+        match slot {
+            Some(slot) => Entry::Occupied(OccupiedEntry {
+                bucket,
+                slot,
+                len: &mut self.len,
+            }),
+            None => Entry::Vacant(VacantEntry {
+                bucket,
+                key,
+                len: &mut self.len,
+            }),
+        }
+    }
+}
+
+impl<K: AsRef<[u8]> + Eq, V> Extend<(K, V)> for HolidayHashMap<K, V> {
+    fn extend<T: IntoIterator<Item = (K, V)>>(&mut self, iter: T) {
+        for (k, v) in iter {
+            self.insert(k, v);
+        }
+    }
+}
+
+impl<K: AsRef<[u8]> + Eq, V> FromIterator<(K, V)> for HolidayHashMap<K, V> {
+    fn from_iter<T: IntoIterator<Item = (K, V)>>(iter: T) -> Self {
+        let mut map = Self::new();
+        map.extend(iter);
+        map
+    }
+}
+
+impl HolidayHashMap<String, usize> {
+    /// Runs a single step of the initialization sequence
+    pub fn apply(&mut self, inst: Instruction) {
+        match inst.op {
+            Operation::Remove => {
+                self.remove(inst.id.as_str());
+            }
+            Operation::Set(len) => {
+                self.insert(inst.id, len);
+            }
+        }
+    }
+
+    /// The sum of every lens's box number (plus one) times its slot number
+    /// (plus one) times its focal length
+    #[must_use]
+    pub fn focusing_power(&self) -> usize {
+        self.slots()
+            .map(|(b, s, _, &len)| (b + 1) * (s + 1) * len)
+            .sum()
+    }
+}
+
+impl Extend<Instruction> for HolidayHashMap<String, usize> {
+    fn extend<T: IntoIterator<Item = Instruction>>(&mut self, iter: T) {
+        for inst in iter {
+            self.apply(inst);
+        }
+    }
+}
+
+impl FromIterator<Instruction> for HolidayHashMap<String, usize> {
+    fn from_iter<T: IntoIterator<Item = Instruction>>(iter: T) -> Self {
+        let mut map = Self::new();
+        map.extend(iter);
+        map
+    }
+}
+
+/// Prints the non-empty boxes like the puzzle does: `Box 0: [rn 1] [cm 2]`
+impl<K: Display, V: Display> std::fmt::Debug for HolidayHashMap<K, V> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (b, bucket) in self.boxes.iter().enumerate() {
+            if bucket.is_empty() {
+                continue;
+            }
+            write!(f, "Box {b}:")?;
+            for (k, v) in bucket {
+                write!(f, " [{k} {v}]")?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+pub enum Entry<'a, K, V> {
+    Occupied(OccupiedEntry<'a, K, V>),
+    Vacant(VacantEntry<'a, K, V>),
+}
+
+pub struct OccupiedEntry<'a, K, V> {
+    bucket: &'a mut Vec<(K, V)>,
+    slot: usize,
+    len: &'a mut usize,
+}
+
+pub struct VacantEntry<'a, K, V> {
+    bucket: &'a mut Vec<(K, V)>,
+    key: K,
+    len: &'a mut usize,
+}
+
+impl<'a, K, V> Entry<'a, K, V> {
+    #[must_use]
+    pub fn key(&self) -> &K {
+        match self {
+            Entry::Occupied(e) => e.key(),
+            Entry::Vacant(e) => &e.key,
+        }
+    }
+
+    pub fn or_insert(self, default: V) -> &'a mut V {
+        self.or_insert_with(|| default)
+    }
+
+    pub fn or_insert_with<F: FnOnce() -> V>(self, default: F) -> &'a mut V {
+        match self {
+            Entry::Occupied(e) => e.into_mut(),
+            Entry::Vacant(e) => e.insert(default()),
+        }
+    }
+
+    #[must_use]
+    pub fn and_modify<F: FnOnce(&mut V)>(mut self, f: F) -> Self {
+        if let Entry::Occupied(e) = &mut self {
+            f(e.get_mut());
+        }
+        self
+    }
+}
+
+impl<'a, K, V> OccupiedEntry<'a, K, V> {
+    #[must_use]
+    pub fn key(&self) -> &K {
+        &self.bucket[self.slot].0
+    }
+
+    #[must_use]
+    pub fn get(&self) -> &V {
+        &self.bucket[self.slot].1
+    }
+
+    pub fn get_mut(&mut self) -> &mut V {
+        &mut self.bucket[self.slot].1
+    }
+
+    #[must_use]
+    pub fn into_mut(self) -> &'a mut V {
+        &mut self.bucket[self.slot].1
+    }
+
+    /// Takes the entry out of its box, shifting everything behind it forward
+    pub fn remove(self) -> V {
+        *self.len -= 1;
+        self.bucket.remove(self.slot).1
+    }
+}
+
+impl<'a, K, V> VacantEntry<'a, K, V> {
+    /// Puts the value at the back of the box
+    pub fn insert(self, value: V) -> &'a mut V {
+        *self.len += 1;
+        self.bucket.push((self.key, value));
+        &mut self.bucket.last_mut().unwrap().1
+    }
+}
+
+/// What one step of the initialization sequence did
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceStep {
+    /// Counting from 1, so this is also how many steps have run
+    pub step: usize,
+    pub instruction: Instruction,
+    /// The box the instruction went to
+    pub bucket: u8,
+    /// Everything in that box afterwards, in slot order
+    pub contents: Vec<(String, usize)>,
+}
+
+/// Prints like the puzzle's walkthrough, but only the box that changed
+impl Display for TraceStep {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "After \"{}\":", self.instruction)?;
+        write!(f, "Box {}:", self.bucket)?;
+        for (k, v) in &self.contents {
+            write!(f, " [{k} {v}]")?;
+        }
+        writeln!(f)
+    }
+}
+
+/// Replays an initialization sequence one instruction at a time. As an
+/// iterator, it yields a `TraceStep` for each instruction it runs.
+#[derive(Debug, Clone)]
+pub struct Replay {
+    steps: Vec<Instruction>,
+    map: HolidayHashMap<String, usize>,
+    applied: usize,
+}
+
+impl Replay {
+    pub fn new<I: IntoIterator<Item = Instruction>>(steps: I) -> Self {
+        Replay {
+            steps: steps.into_iter().collect(),
+            map: HolidayHashMap::new(),
+            applied: 0,
+        }
+    }
+
+    /// The boxes as they are right now
+    #[must_use]
+    pub fn boxes(&self) -> &HolidayHashMap<String, usize> {
+        &self.map
+    }
+
+    /// How many steps have run so far
+    #[must_use]
+    pub fn applied(&self) -> usize {
+        self.applied
+    }
+
+    /// Runs (or rewinds) until exactly `n` steps have run, stopping early if
+    /// the sequence runs out
+    pub fn jump_to(&mut self, n: usize) -> &HolidayHashMap<String, usize> {
+        if n < self.applied {
+            self.map = HolidayHashMap::new();
+            self.applied = 0;
+        }
+        while self.applied < n && self.next().is_some() {}
+        &self.map
+    }
+
+    /// Only the steps that touched box `b`
+    pub fn for_box(self, b: u8) -> impl Iterator<Item = TraceStep> {
+        self.filter(move |t| t.bucket == b)
+    }
+
+    /// Only the steps for one lens
+    pub fn for_label(self, label: &str) -> impl Iterator<Item = TraceStep> + '_ {
+        self.filter(move |t| t.instruction.id == label)
+    }
+}
+
+impl Iterator for Replay {
+    type Item = TraceStep;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let instruction = self.steps.get(self.applied)?.clone();
+        let bucket = self.map.box_of(&instruction.id);
+        self.map.apply(instruction.clone());
+        self.applied += 1;
+        Some(TraceStep {
+            step: self.applied,
+            instruction,
+            bucket,
+            contents: self.map.bucket(bucket).to_vec(),
+        })
+    }
 }
 
 #[aoc(day15, part1)]
 pub fn part1(input: &str) -> usize {
-    input.replace('\n', "").split(',').map(digest).sum()
+    input
+        .replace('\n', "")
+        .split(',')
+        .map(|s| usize::from(digest(s.as_bytes())))
+        .sum()
 }
 
 #[aoc(day15, part2)]
 pub fn part2(input: &str) -> usize {
-    let steps: Vec<Instruction> = input
+    input
         .replace('\n', "")
         .split(',')
-        .map(|s| s.parse().unwrap())
-        .collect();
-    // println!("{:?}", steps);
+        .map(|s| s.parse::<Instruction>().unwrap())
+        .collect::<HolidayHashMap<_, _>>()
+        .focusing_power()
+}
 
-    let mut map = vec![vec![]; 256];
-    for s in steps {
-        let bucket = &mut map[digest(&s.id)];
-        let idx = bucket.iter().position(|i: &(String, usize)| i.0 == s.id);
+#[cfg(test)]
+mod test {
+    use super::{digest, part1, part2, Entry, HolidayHashMap, Instruction, Replay, TraceStep};
 
-        match s.op {
-            Operation::Remove => {
-                if let Some(idx) = idx {
-                    bucket.remove(idx);
-                }
-            }
-            Operation::Set(len) => {
-                if let Some(idx) = idx {
-                    bucket[idx] = (s.id, len);
-                } else {
-                    bucket.push((s.id, len));
-                }
+    const EXAMPLE: &str = "rn=1,cm-,qp=3,cm=2,qp-,pc=4,ot=9,ab=5,pc-,pc=6,ot=7";
+
+    #[test]
+    pub fn examples() {
+        assert_eq!(digest(b"HASH"), 52);
+        assert_eq!(part1(EXAMPLE), 1320);
+        assert_eq!(part2(EXAMPLE), 145);
+    }
+
+    #[test]
+    pub fn debug_format() {
+        let map: HolidayHashMap<_, _> = EXAMPLE
+            .split(',')
+            .map(|s| s.parse::<Instruction>().unwrap())
+            .collect();
+        assert_eq!(
+            format!("{map:?}"),
+            "Box 0: [rn 1] [cm 2]\nBox 3: [ot 7] [ab 5] [pc 6]\n"
+        );
+        assert_eq!(format!("{:?}", HolidayHashMap::<String, usize>::new()), "");
+    }
+
+    #[test]
+    pub fn slot_order() {
+        // Everything lands in one box, so slots are pure insertion order
+        let mut map = HolidayHashMap::with_hasher(|_| 7);
+        map.extend([("a", 1), ("b", 2), ("c", 3)]);
+        assert_eq!(map.insert("b", 20), Some(2));
+        assert_eq!(map.bucket(7), [("a", 1), ("b", 20), ("c", 3)]);
+
+        assert_eq!(map.remove("a"), Some(1));
+        assert_eq!(map.remove("a"), None);
+        assert_eq!(map.insert("a", 10), None);
+        assert_eq!(map.bucket(7), [("b", 20), ("c", 3), ("a", 10)]);
+        assert_eq!(
+            map.slots()
+                .map(|(b, s, k, _)| (b, s, *k))
+                .collect::<Vec<_>>(),
+            [(7, 0, "b"), (7, 1, "c"), (7, 2, "a")]
+        );
+        assert_eq!(map.len(), 3);
+        assert!(map.bucket(0).is_empty());
+    }
+
+    #[test]
+    pub fn entries() {
+        let mut map = HolidayHashMap::with_hasher(|_| 0);
+        *map.entry("x").or_insert(1) += 1;
+        *map.entry("y").or_insert_with(|| 5) += 1;
+        map.entry("x").and_modify(|v| *v *= 10).or_insert(0);
+        map.entry("z").and_modify(|v| *v *= 10).or_insert(3);
+        assert_eq!(map.bucket(0), [("x", 20), ("y", 6), ("z", 3)]);
+
+        match map.entry("y") {
+            Entry::Occupied(e) => {
+                assert_eq!((e.key(), e.get()), (&"y", &6));
+                assert_eq!(e.remove(), 6);
             }
+            Entry::Vacant(_) => panic!("y should be occupied"),
+        }
+        assert!(matches!(map.entry("y"), Entry::Vacant(_)));
+        assert_eq!(map.bucket(0), [("x", 20), ("z", 3)]);
+        assert_eq!(map.len(), 2);
+        assert!(map.contains_key("z") && !map.contains_key("y"));
+        assert_eq!(map.get("x"), Some(&20));
+    }
+
+    fn example() -> Replay {
+        Replay::new(
+            EXAMPLE
+                .split(',')
+                .map(|s| s.parse::<Instruction>().unwrap()),
+        )
+    }
+
+    #[test]
+    pub fn trace() {
+        let steps: Vec<_> = example().collect();
+        assert_eq!(steps.len(), 11);
+        assert_eq!(
+            steps.iter().map(|t| t.bucket).collect::<Vec<_>>(),
+            [0, 0, 1, 0, 1, 3, 3, 3, 3, 3, 3]
+        );
+        assert_eq!(steps[4].contents, []);
+        assert_eq!(
+            steps[10].to_string(),
+            "After \"ot=7\":\nBox 3: [ot 7] [ab 5] [pc 6]\n"
+        );
+        assert!(steps.iter().enumerate().all(|(i, t)| t.step == i + 1));
+    }
+
+    #[test]
+    pub fn filters() {
+        let steps = |it: &mut dyn Iterator<Item = TraceStep>| -> Vec<usize> {
+            it.map(|t| t.step).collect()
         };
+        assert_eq!(steps(&mut example().for_box(0)), [1, 2, 4]);
+        assert_eq!(steps(&mut example().for_box(1)), [3, 5]);
+        assert_eq!(steps(&mut example().for_box(2)), []);
+        assert_eq!(steps(&mut example().for_label("pc")), [6, 9, 10]);
+        assert_eq!(steps(&mut example().for_label("zz")), []);
+    }
+
+    #[test]
+    pub fn jumps() {
+        let mut replay = example();
+        assert_eq!(replay.jump_to(11).focusing_power(), 145);
+        let after_four = format!("{:?}", replay.jump_to(4));
+        assert_eq!(after_four, "Box 0: [rn 1] [cm 2]\nBox 1: [qp 3]\n");
+        assert_eq!(replay.applied(), 4);
+
+        // Rewinding and replaying lands in the same state as going straight there
+        let mut fresh = example();
+        replay.jump_to(9);
+        replay.jump_to(2);
+        fresh.jump_to(2);
+        assert_eq!(
+            format!("{:?}", replay.boxes()),
+            format!("{:?}", fresh.boxes())
+        );
+        assert_eq!(format!("{:?}", replay.boxes()), "Box 0: [rn 1]\n");
+
+        assert_eq!(replay.jump_to(100).len(), 5);
+        assert_eq!(replay.applied(), 11);
+        assert!(replay.next().is_none());
+        assert!(replay.jump_to(0).is_empty());
+        assert_eq!(replay.next().map(|t| t.step), Some(1));
     }
-    map.iter()
-        .enumerate()
-        .map(|(b, bucket)| total_bucket(bucket, b))
-        .sum()
 }
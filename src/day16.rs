@@ -1,6 +1,10 @@
 use aoc_runner_derive::{aoc, aoc_generator};
-use rayon::prelude::*;
-use std::collections::HashSet;
+use petgraph::{algo::tarjan_scc, Graph};
+use std::{
+    collections::{HashMap, HashSet},
+    io::Write,
+    path::Path,
+};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Tile {
@@ -24,7 +28,7 @@ impl From<char> for Tile {
     }
 }
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-enum Direction {
+pub enum Direction {
     Up,
     Down,
     Left,
@@ -32,7 +36,36 @@ enum Direction {
 }
 
 impl Direction {
-    pub fn add(self, posn: (usize, usize), tiles: &[Vec<Tile>]) -> Option<(usize, usize)> {
+    /// This direction's bit in a cell's visited mask
+    fn bit(self) -> u8 {
+        1 << self as u8
+    }
+
+    #[must_use]
+    pub fn turn_left(self) -> Direction {
+        match self {
+            Direction::Up => Direction::Left,
+            Direction::Left => Direction::Down,
+            Direction::Down => Direction::Right,
+            Direction::Right => Direction::Up,
+        }
+    }
+
+    #[must_use]
+    pub fn turn_right(self) -> Direction {
+        self.turn_left().turn_left().turn_left()
+    }
+
+    fn arrow(self) -> char {
+        match self {
+            Direction::Up => '^',
+            Direction::Down => 'v',
+            Direction::Left => '<',
+            Direction::Right => '>',
+        }
+    }
+
+    pub fn add<T>(self, posn: (usize, usize), tiles: &[Vec<T>]) -> Option<(usize, usize)> {
         if self == Direction::Up {
             if posn.0 == 0 {
                 None
@@ -64,9 +97,9 @@ impl Direction {
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
-struct Ray {
-    posn: (usize, usize),
-    dir: Direction,
+pub struct Ray {
+    pub posn: (usize, usize),
+    pub dir: Direction,
 }
 
 impl Default for Ray {
@@ -77,137 +110,660 @@ impl Default for Ray {
         }
     }
 }
-fn get_energized(
-    seed: &Ray,
-    tiles: &[Vec<Tile>],
-    memo: &mut HashSet<Ray>,
-) -> HashSet<(usize, usize)> {
-    // visited != memo because memo has to keep in account direction
-    let mut visited = HashSet::new();
-    let mut ray = *seed;
-    loop {
-        if memo.contains(&ray) {
-            break visited;
-        }
-        visited.insert(ray.posn);
-        memo.insert(ray);
-        let (r, c) = ray.posn;
-        let tile = &tiles[r][c];
-        match (tile, ray.dir) {
+
+/// Anything that can sit in a cell of a contraption
+pub trait Element {
+    /// Pushes onto `out` every beam that leaves this element when a beam
+    /// enters it at `posn` heading `dir`. Each is the cell the beam leaves
+    /// from (usually `posn`) and the way it's heading; nothing at all means
+    /// the beam stops here.
+    fn emit(&self, posn: (usize, usize), dir: Direction, out: &mut Vec<Ray>);
+
+    /// How to draw the element
+    fn symbol(&self) -> char;
+}
+
+impl Tile {
+    /// Where a beam heading `dir` goes after passing through this tile
+    fn outgoing(self, dir: Direction) -> &'static [Direction] {
+        match (self, dir) {
             (Tile::Empty, _)
             | (Tile::SplitHorizontal, Direction::Left | Direction::Right)
-            | (Tile::SplitVertical, Direction::Up | Direction::Down) => {
-                if let Some(tmp) = ray.dir.add(ray.posn, tiles) {
-                    ray.posn = tmp;
-                } else {
-                    break visited;
-                }
-            }
+            | (Tile::SplitVertical, Direction::Up | Direction::Down) => match dir {
+                Direction::Up => &[Direction::Up],
+                Direction::Down => &[Direction::Down],
+                Direction::Left => &[Direction::Left],
+                Direction::Right => &[Direction::Right],
+            },
             (Tile::AngleDown, Direction::Right) | (Tile::AngleUp, Direction::Left) => {
-                ray.dir = Direction::Down;
-                if let Some(tmp) = ray.dir.add(ray.posn, tiles) {
-                    ray.posn = tmp;
-                } else {
-                    break visited;
-                }
+                &[Direction::Down]
             }
             (Tile::AngleDown, Direction::Up) | (Tile::AngleUp, Direction::Down) => {
-                ray.dir = Direction::Left;
-                if let Some(tmp) = ray.dir.add(ray.posn, tiles) {
-                    ray.posn = tmp;
-                } else {
-                    break visited;
-                }
+                &[Direction::Left]
             }
             (Tile::AngleDown, Direction::Left) | (Tile::AngleUp, Direction::Right) => {
-                ray.dir = Direction::Up;
-                if let Some(tmp) = ray.dir.add(ray.posn, tiles) {
-                    ray.posn = tmp;
-                } else {
-                    break visited;
-                }
+                &[Direction::Up]
             }
             (Tile::AngleDown, Direction::Down) | (Tile::AngleUp, Direction::Up) => {
-                ray.dir = Direction::Right;
-                if let Some(tmp) = ray.dir.add(ray.posn, tiles) {
-                    ray.posn = tmp;
-                } else {
-                    break visited;
-                }
+                &[Direction::Right]
             }
             (Tile::SplitVertical, Direction::Left | Direction::Right) => {
-                let ray_up = Ray {
-                    dir: Direction::Up,
-                    posn: ray.posn,
-                };
-                let ray_down = Ray {
-                    dir: Direction::Down,
-                    posn: ray.posn,
-                };
-                visited.extend(get_energized(&ray_up, tiles, memo));
-                visited.extend(get_energized(&ray_down, tiles, memo));
-                break visited;
+                &[Direction::Up, Direction::Down]
             }
             (Tile::SplitHorizontal, Direction::Up | Direction::Down) => {
-                let ray_left = Ray {
-                    dir: Direction::Left,
-                    posn: ray.posn,
-                };
-                let ray_right = Ray {
-                    dir: Direction::Right,
-                    posn: ray.posn,
-                };
-                visited.extend(get_energized(&ray_left, tiles, memo));
-                visited.extend(get_energized(&ray_right, tiles, memo));
-                break visited;
+                &[Direction::Left, Direction::Right]
             }
         }
     }
 }
 
-#[aoc_generator(day16)]
-pub fn gen(input: &str) -> Vec<Vec<Tile>> {
+impl Element for Tile {
+    fn emit(&self, posn: (usize, usize), dir: Direction, out: &mut Vec<Ray>) {
+        out.extend(self.outgoing(dir).iter().map(|&dir| Ray { posn, dir }));
+    }
+
+    fn symbol(&self) -> char {
+        match self {
+            Tile::Empty => '.',
+            Tile::AngleUp => '/',
+            Tile::AngleDown => '\\',
+            Tile::SplitVertical => '|',
+            Tile::SplitHorizontal => '-',
+        }
+    }
+}
+
+impl<E: Element + ?Sized> Element for Box<E> {
+    fn emit(&self, posn: (usize, usize), dir: Direction, out: &mut Vec<Ray>) {
+        (**self).emit(posn, dir, out);
+    }
+
+    fn symbol(&self) -> char {
+        (**self).symbol()
+    }
+}
+
+/// Soaks up any beam that hits it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Absorber;
+
+impl Element for Absorber {
+    fn emit(&self, _: (usize, usize), _: Direction, _: &mut Vec<Ray>) {}
+
+    fn symbol(&self) -> char {
+        'X'
+    }
+}
+
+/// Splits every beam three ways: straight on, and out to either side
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Prism;
+
+impl Element for Prism {
+    fn emit(&self, posn: (usize, usize), dir: Direction, out: &mut Vec<Ray>) {
+        for dir in [dir.turn_left(), dir, dir.turn_right()] {
+            out.push(Ray { posn, dir });
+        }
+    }
+
+    fn symbol(&self) -> char {
+        '+'
+    }
+}
+
+/// A mirror that's only silvered on one face. Beams hitting that face bounce
+/// like they would off `mirror`; beams hitting the back go straight through.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct OneWayMirror {
+    /// `Tile::AngleUp` or `Tile::AngleDown`
+    pub mirror: Tile,
+    /// One of the directions a beam can be heading to hit the silvered face
+    pub silvered: Direction,
+}
+
+impl OneWayMirror {
+    /// Whether a beam heading `dir` hits the same face as one heading `other`
+    fn same_face(self, dir: Direction, other: Direction) -> bool {
+        // Bouncing off the face sends both beams out of the same face, so
+        // they meet in the middle
+        dir == other || self.mirror.outgoing(dir)[0] == other.turn_left().turn_left()
+    }
+}
+
+impl Element for OneWayMirror {
+    fn emit(&self, posn: (usize, usize), dir: Direction, out: &mut Vec<Ray>) {
+        if self.same_face(dir, self.silvered) {
+            self.mirror.emit(posn, dir, out);
+        } else {
+            out.push(Ray { posn, dir });
+        }
+    }
+
+    fn symbol(&self) -> char {
+        match self.mirror {
+            Tile::AngleUp => 'F',
+            _ => 'B',
+        }
+    }
+}
+
+/// Sends every beam out of its partner portal, still heading the same way
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Portal {
+    pub partner: (usize, usize),
+}
+
+impl Element for Portal {
+    fn emit(&self, _: (usize, usize), dir: Direction, out: &mut Vec<Ray>) {
+        out.push(Ray {
+            posn: self.partner,
+            dir,
+        });
+    }
+
+    fn symbol(&self) -> char {
+        '@'
+    }
+}
+
+/// Parses a contraption with extra elements. `extra` gets first pick of every
+/// character (and where it is), and anything it passes on is a regular tile.
+pub fn parse_with<F>(input: &str, extra: F) -> Vec<Vec<Box<dyn Element>>>
+where
+    F: Fn((usize, usize), char) -> Option<Box<dyn Element>>,
+{
     input
         .lines()
-        .map(|l| l.chars().map(std::convert::Into::into).collect())
+        .enumerate()
+        .map(|(r, l)| {
+            l.chars()
+                .enumerate()
+                .map(|(c, ch)| {
+                    extra((r, c), ch)
+                        .unwrap_or_else(|| Box::new(Tile::from(ch)) as Box<dyn Element>)
+                })
+                .collect()
+        })
         .collect()
 }
 
-#[aoc(day16, part1)]
-pub fn part1(tiles: &[Vec<Tile>]) -> usize {
-    let mut memo = HashSet::new();
-    get_energized(&Ray::default(), tiles, &mut memo).len()
+/// Moves every beam that `ray` sets off one step, lighting up the cells they
+/// leave from and pushing the rays that enter a new cell
+fn step<T: Element>(ray: Ray, tiles: &[Vec<T>], lit: &mut [bool], next: &mut Vec<Ray>) {
+    let cols = tiles[0].len();
+    let (r, c) = ray.posn;
+    let mut emitted = vec![];
+    tiles[r][c].emit(ray.posn, ray.dir, &mut emitted);
+    for out in emitted {
+        lit[out.posn.0 * cols + out.posn.1] = true;
+        if let Some(posn) = out.dir.add(out.posn, tiles) {
+            next.push(Ray { posn, dir: out.dir });
+        }
+    }
 }
 
-#[aoc(day16, part2)]
-pub fn part2(tiles: &[Vec<Tile>]) -> usize {
-    (0..tiles.len())
-        .flat_map(|c| {
+/// Traces every beam that comes from `seed`, returning which cells lit up
+/// (row-major). Beams wait their turn on a worklist instead of recursing at
+/// each splitter, and a beam stops as soon as it's somewhere some beam has
+/// already been, heading the same way; that's tracked as a bitmask of
+/// directions per cell.
+fn trace<T: Element>(seed: Ray, tiles: &[Vec<T>]) -> Vec<bool> {
+    let cols = tiles[0].len();
+    let mut visited = vec![0u8; tiles.len() * cols];
+    let mut lit = vec![false; tiles.len() * cols];
+    let mut worklist = vec![seed];
+    while let Some(ray) = worklist.pop() {
+        let (r, c) = ray.posn;
+        let seen = &mut visited[r * cols + c];
+        if *seen & ray.dir.bit() != 0 {
+            continue;
+        }
+        *seen |= ray.dir.bit();
+        lit[r * cols + c] = true;
+        step(ray, tiles, &mut lit, &mut worklist);
+    }
+    lit
+}
+
+#[must_use]
+pub fn get_energized<T: Element>(seed: Ray, tiles: &[Vec<T>]) -> usize {
+    trace(seed, tiles).into_iter().filter(|&v| v).count()
+}
+
+/// Every way a beam can enter a `rows` by `cols` contraption from the edge:
+/// down from the top row, up from the bottom, right from the left column and
+/// left from the right. Corners show up once per side they're on.
+pub fn edge_entries(rows: usize, cols: usize) -> impl Iterator<Item = Ray> {
+    let (last_row, last_col) = (rows.saturating_sub(1), cols.saturating_sub(1));
+    (0..cols)
+        .flat_map(move |c| {
             [
                 Ray {
                     posn: (0, c),
                     dir: Direction::Down,
                 },
                 Ray {
-                    posn: (tiles.len() - 1, c),
+                    posn: (last_row, c),
                     dir: Direction::Up,
                 },
             ]
         })
-        .chain((0..tiles[0].len() - 1).flat_map(|r| {
+        .chain((0..rows).flat_map(move |r| {
             [
                 Ray {
                     posn: (r, 0),
                     dir: Direction::Right,
                 },
                 Ray {
-                    posn: (r, tiles[0].len() - 1),
+                    posn: (r, last_col),
                     dir: Direction::Left,
                 },
             ]
         }))
-        .par_bridge()
-        .map(|r| get_energized(&r, tiles, &mut HashSet::new()).len())
-        .max()
-        .unwrap()
+}
+
+/// Follows a single beam from `start` for as long as it stays a single beam:
+/// until an element splits or absorbs it, it leaves the contraption, or it
+/// starts going in circles. Returns the cells it lit (row-major), and the
+/// beams it split into, if any.
+fn walk<T: Element>(start: Ray, tiles: &[Vec<T>]) -> (Vec<usize>, Vec<Ray>) {
+    let cols = tiles[0].len();
+    let mut cells = vec![];
+    let mut seen = HashSet::new();
+    let mut emitted = vec![];
+    let mut ray = start;
+    loop {
+        if !seen.insert(ray) {
+            break (cells, vec![]);
+        }
+        let (r, c) = ray.posn;
+        cells.push(r * cols + c);
+        emitted.clear();
+        tiles[r][c].emit(ray.posn, ray.dir, &mut emitted);
+        let [out] = emitted[..] else {
+            break (cells, emitted);
+        };
+        cells.push(out.posn.0 * cols + out.posn.1);
+        match out.dir.add(out.posn, tiles) {
+            Some(posn) => ray = Ray { posn, dir: out.dir },
+            None => break (cells, vec![]),
+        }
+    }
+}
+
+/// Where a beam leaving `out` goes: the cell it leaves from, then wherever
+/// `walk` takes it
+fn follow<T: Element>(out: Ray, tiles: &[Vec<T>]) -> (Vec<usize>, Vec<Ray>) {
+    let cell = out.posn.0 * tiles[0].len() + out.posn.1;
+    match out.dir.add(out.posn, tiles) {
+        Some(posn) => {
+            let (mut cells, split) = walk(Ray { posn, dir: out.dir }, tiles);
+            cells.push(cell);
+            (cells, split)
+        }
+        None => (vec![cell], vec![]),
+    }
+}
+
+/// Every beam segment reachable from the edge of a contraption, where a
+/// segment is the path a beam takes from where something split it until
+/// the next split. Segments are keyed by the beam that starts them. Those
+/// that feed each other in a loop are collapsed into a single component,
+/// and each component knows every cell lit by it and everything downstream
+/// of it. That makes any entry's answer a walk to the first split, then a
+/// union of a few precomputed cell sets.
+#[derive(Debug, Clone)]
+pub struct BeamGraph<'a, T> {
+    tiles: &'a [Vec<T>],
+    /// 64-bit words per cell set
+    words: usize,
+    /// The component each segment ended up in
+    components: HashMap<Ray, usize>,
+    /// Every cell lit by the component or anything it feeds
+    reach: Vec<Vec<u64>>,
+}
+
+impl<'a, T: Element> BeamGraph<'a, T> {
+    #[must_use]
+    pub fn new(tiles: &'a [Vec<T>]) -> Self {
+        let words = (tiles.len() * tiles[0].len()).div_ceil(64);
+
+        let mut graph: Graph<Vec<usize>, ()> = Graph::new();
+        let mut nodes = HashMap::new();
+        let mut splits = vec![];
+        let mut pending: Vec<Ray> = edge_entries(tiles.len(), tiles[0].len())
+            .flat_map(|entry| walk(entry, tiles).1)
+            .collect();
+        while let Some(out) = pending.pop() {
+            if nodes.contains_key(&out) {
+                continue;
+            }
+            let (cells, split) = follow(out, tiles);
+            nodes.insert(out, graph.add_node(cells));
+            pending.extend(&split);
+            splits.push((out, split));
+        }
+        for (out, split) in splits {
+            for next in split {
+                graph.add_edge(nodes[&out], nodes[&next], ());
+            }
+        }
+
+        // Components come out in reverse topological order, so everything a
+        // component feeds is already done by the time we get to it
+        let sccs = tarjan_scc(&graph);
+        let mut component_of = vec![0; graph.node_count()];
+        for (i, scc) in sccs.iter().enumerate() {
+            for node in scc {
+                component_of[node.index()] = i;
+            }
+        }
+        let mut reach: Vec<Vec<u64>> = Vec::with_capacity(sccs.len());
+        for (i, scc) in sccs.iter().enumerate() {
+            let mut cells = vec![0u64; words];
+            for &node in scc {
+                for &cell in &graph[node] {
+                    cells[cell / 64] |= 1 << (cell % 64);
+                }
+                for next in graph.neighbors(node) {
+                    let j = component_of[next.index()];
+                    if j != i {
+                        for (w, &bits) in cells.iter_mut().zip(&reach[j]) {
+                            *w |= bits;
+                        }
+                    }
+                }
+            }
+            reach.push(cells);
+        }
+
+        let components = nodes
+            .into_iter()
+            .map(|(ray, node)| (ray, component_of[node.index()]))
+            .collect();
+        BeamGraph {
+            tiles,
+            words,
+            components,
+            reach,
+        }
+    }
+
+    /// How many cells end up energized when a beam enters at `entry`. Entries
+    /// from the edge only use precomputed segments; anything else might find
+    /// new ones, in which case we just trace it from scratch.
+    #[must_use]
+    pub fn energized(&self, entry: Ray) -> usize {
+        let (cells, split) = walk(entry, self.tiles);
+        let mut lit = vec![0u64; self.words];
+        for cell in cells {
+            lit[cell / 64] |= 1 << (cell % 64);
+        }
+        for out in split {
+            let Some(&comp) = self.components.get(&out) else {
+                return get_energized(entry, self.tiles);
+            };
+            for (w, &bits) in lit.iter_mut().zip(&self.reach[comp]) {
+                *w |= bits;
+            }
+        }
+        lit.iter().map(|w| w.count_ones() as usize).sum()
+    }
+
+    /// Every edge entry and how many cells it energizes
+    #[must_use]
+    pub fn all_entries(&self) -> Vec<(Ray, usize)> {
+        edge_entries(self.tiles.len(), self.tiles[0].len())
+            .map(|ray| (ray, self.energized(ray)))
+            .collect()
+    }
+
+    /// The edge entry that energizes the most cells
+    #[must_use]
+    pub fn best_entry(&self) -> (Ray, usize) {
+        self.all_entries()
+            .into_iter()
+            .max_by_key(|&(_, count)| count)
+            .unwrap()
+    }
+}
+
+/// The beams as they spread out, one step at a time. Each frame has the beam
+/// fronts (the beams that just entered a cell) and everything lit so far.
+#[derive(Debug, Clone)]
+pub struct Animation<'a, T> {
+    tiles: &'a [Vec<T>],
+    visited: Vec<u8>,
+    lit: Vec<bool>,
+    fronts: Vec<Ray>,
+    step: usize,
+}
+
+impl<'a, T: Element> Animation<'a, T> {
+    #[must_use]
+    pub fn new(seed: Ray, tiles: &'a [Vec<T>]) -> Self {
+        Animation {
+            tiles,
+            visited: vec![0; tiles.len() * tiles[0].len()],
+            lit: vec![false; tiles.len() * tiles[0].len()],
+            fronts: vec![seed],
+            step: 0,
+        }
+    }
+
+    /// Writes every remaining frame to `dir` as `frame_00000.ppm`,
+    /// `frame_00001.ppm`, and so on, returning how many there were
+    pub fn write_ppm_sequence(self, dir: &Path, scale: usize) -> std::io::Result<usize> {
+        let mut count = 0;
+        for frame in self {
+            let file = std::fs::File::create(dir.join(format!("frame_{:05}.ppm", frame.step)))?;
+            frame.write_ppm(&mut std::io::BufWriter::new(file), scale)?;
+            count += 1;
+        }
+        Ok(count)
+    }
+}
+
+impl<'a, T: Element> Iterator for Animation<'a, T> {
+    type Item = Frame<'a, T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let cols = self.tiles[0].len();
+        // Only beams nothing has been through yet are worth showing
+        let mut fronts = vec![];
+        for ray in std::mem::take(&mut self.fronts) {
+            let seen = &mut self.visited[ray.posn.0 * cols + ray.posn.1];
+            if *seen & ray.dir.bit() == 0 {
+                *seen |= ray.dir.bit();
+                fronts.push(ray);
+            }
+        }
+        if fronts.is_empty() {
+            return None;
+        }
+        for &ray in &fronts {
+            self.lit[ray.posn.0 * cols + ray.posn.1] = true;
+            step(ray, self.tiles, &mut self.lit, &mut self.fronts);
+        }
+        self.step += 1;
+        Some(Frame {
+            tiles: self.tiles,
+            step: self.step - 1,
+            fronts,
+            lit: self.lit.clone(),
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Frame<'a, T> {
+    tiles: &'a [Vec<T>],
+    pub step: usize,
+    pub fronts: Vec<Ray>,
+    lit: Vec<bool>,
+}
+
+impl<T: Element> Frame<'_, T> {
+    #[must_use]
+    pub fn energized(&self) -> usize {
+        self.lit.iter().filter(|&&l| l).count()
+    }
+
+    /// Writes the frame as a binary PPM, `scale` pixels per cell: beam fronts
+    /// in red, lit cells in yellow (darker if there's an element there),
+    /// unlit elements in grey and everything else near-black
+    pub fn write_ppm<W: Write>(&self, out: &mut W, scale: usize) -> std::io::Result<()> {
+        let (rows, cols) = (self.tiles.len(), self.tiles[0].len());
+        let fronts: HashSet<_> = self.fronts.iter().map(|r| r.posn).collect();
+        write!(out, "P6\n{} {}\n255\n", cols * scale, rows * scale)?;
+        for (r, row) in self.tiles.iter().enumerate() {
+            let pixels: Vec<[u8; 3]> = row
+                .iter()
+                .enumerate()
+                .map(|(c, tile)| {
+                    let element = tile.symbol() != '.';
+                    match (fronts.contains(&(r, c)), self.lit[r * cols + c], element) {
+                        (true, _, _) => [255, 64, 64],
+                        (false, true, true) => [255, 160, 0],
+                        (false, true, false) => [255, 220, 64],
+                        (false, false, true) => [160, 160, 160],
+                        (false, false, false) => [16, 16, 32],
+                    }
+                })
+                .collect();
+            for _ in 0..scale {
+                for px in &pixels {
+                    for _ in 0..scale {
+                        out.write_all(px)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Draws beam fronts as arrows and lit cells as `#`, like the puzzle does
+impl<T: Element> std::fmt::Display for Frame<'_, T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let cols = self.tiles[0].len();
+        let fronts: HashMap<_, _> = self.fronts.iter().map(|r| (r.posn, r.dir)).collect();
+        for (r, row) in self.tiles.iter().enumerate() {
+            for (c, tile) in row.iter().enumerate() {
+                let ch = match fronts.get(&(r, c)) {
+                    Some(dir) => dir.arrow(),
+                    None if tile.symbol() != '.' => tile.symbol(),
+                    None if self.lit[r * cols + c] => '#',
+                    None => '.',
+                };
+                write!(f, "{ch}")?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+#[aoc_generator(day16)]
+pub fn gen(input: &str) -> Vec<Vec<Tile>> {
+    input
+        .lines()
+        .map(|l| l.chars().map(std::convert::Into::into).collect())
+        .collect()
+}
+
+#[aoc(day16, part1)]
+pub fn part1(tiles: &[Vec<Tile>]) -> usize {
+    get_energized(Ray::default(), tiles)
+}
+
+#[aoc(day16, part2)]
+pub fn part2(tiles: &[Vec<Tile>]) -> usize {
+    BeamGraph::new(tiles).best_entry().1
+}
+
+#[cfg(test)]
+mod test {
+    use super::{edge_entries, gen, get_energized, part1, part2, BeamGraph, Tile};
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    const EXAMPLE: &str = r".|...\....
+|.-.\.....
+.....|-...
+........|.
+..........
+.........\
+..../.\\..
+.-.-/..|..
+.|....-|.\
+..//.|....";
+
+    fn check_every_entry(tiles: &[Vec<Tile>]) {
+        let graph = BeamGraph::new(tiles);
+        for entry in edge_entries(tiles.len(), tiles[0].len()) {
+            assert_eq!(
+                graph.energized(entry),
+                get_energized(entry, tiles),
+                "{entry:?}"
+            );
+        }
+    }
+
+    #[test]
+    pub fn example() {
+        let tiles = gen(EXAMPLE);
+        assert_eq!(part1(&tiles), 46);
+        assert_eq!(part2(&tiles), 51);
+        check_every_entry(&tiles);
+    }
+
+    #[test]
+    pub fn beam_graph() {
+        let mut rng = StdRng::seed_from_u64(16);
+        for (rows, cols) in [(1, 1), (1, 9), (9, 1), (12, 17), (40, 70)] {
+            let tiles: Vec<Vec<Tile>> = (0..rows)
+                .map(|_| {
+                    (0..cols)
+                        .map(|_| ['.', '.', '.', '/', '\\', '|', '-'][rng.gen_range(0..7)].into())
+                        .collect()
+                })
+                .collect();
+            check_every_entry(&tiles);
+        }
+    }
+
+    #[test]
+    pub fn one_way_mirrors() {
+        use super::{Direction, Element, OneWayMirror, Ray};
+        use Direction::{Down, Left, Right, Up};
+
+        let cases = [
+            (
+                Tile::AngleUp,
+                [(Right, Up), (Down, Left), (Up, Up), (Left, Left)],
+            ),
+            (
+                Tile::AngleDown,
+                [(Right, Down), (Up, Left), (Down, Down), (Left, Left)],
+            ),
+        ];
+        for (mirror, expected) in cases {
+            let element = OneWayMirror {
+                mirror,
+                silvered: Right,
+            };
+            for (dir, out) in expected {
+                let mut emitted = vec![];
+                element.emit((0, 0), dir, &mut emitted);
+                assert_eq!(
+                    emitted,
+                    vec![Ray {
+                        posn: (0, 0),
+                        dir: out
+                    }],
+                    "{mirror:?} {dir:?}"
+                );
+            }
+        }
+    }
 }
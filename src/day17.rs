@@ -1,22 +1,14 @@
-use aoc_runner_derive::aoc;
-use std::collections::{HashMap, HashSet};
-
-use petgraph::algo::dijkstra;
-use petgraph::Graph;
-
-const T1_MAX_STRAIGHT: usize = 3;
-const T2_MIN_STRAIGHT: usize = 4;
-const T2_MAX_STRAIGHT: usize = 10;
-const DIRS: [Direction; 4] = [
-    Direction::Up,
-    Direction::Down,
-    Direction::Left,
-    Direction::Right,
-];
+use aoc_runner_derive::{aoc, aoc_generator};
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap, HashSet},
+    str::FromStr,
+};
+
 const START_DIRS: [Direction; 2] = [Direction::Down, Direction::Right];
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-enum Direction {
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Direction {
     Up,
     Down,
     Left,
@@ -64,223 +56,557 @@ impl Direction {
             Direction::Left | Direction::Right => [Direction::Up, Direction::Down],
         }
     }
+
+    fn arrow(self) -> char {
+        match self {
+            Direction::Up => '^',
+            Direction::Down => 'v',
+            Direction::Left => '<',
+            Direction::Right => '>',
+        }
+    }
+}
+
+/// Where the crucible is, which way it's going, and how many blocks in a row
+/// it's gone that way. A count of 0 means it hasn't moved yet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Block {
+    pub posn: (usize, usize),
+    pub dir: Direction,
+    pub count: usize,
 }
+
+/// How far a crucible has to go in a straight line before it can turn (or
+/// stop), and how far it can go before it has to turn
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-struct Block {
-    posn: (usize, usize),
-    dir: Direction,
-    count: usize,
+pub struct Crucible {
+    pub min_straight: usize,
+    pub max_straight: usize,
 }
 
-#[aoc(day17, part1)]
-pub fn part1(input: &str) -> usize {
-    let blocks: Vec<Vec<usize>> = input
-        .lines()
-        .map(|l| l.chars().map(|c| c.to_string().parse().unwrap()).collect())
-        .collect();
-    let mut graph = Graph::<_, _>::new();
-    let mut nodes = Vec::with_capacity(blocks.len());
-    for (r, block_row) in blocks.iter().enumerate() {
-        let mut row = Vec::with_capacity(blocks[r].len());
-        for c in 0..block_row.len() {
-            let mut col = HashMap::with_capacity(DIRS.len());
-            for d in &DIRS {
-                let mut stack = Vec::with_capacity(3);
-                // Could be one, two, or three
-                for i in 1..=T1_MAX_STRAIGHT {
-                    let idx = graph.add_node(Block {
-                        count: i,
-                        dir: *d,
-                        posn: (r, c),
-                    });
-                    stack.push(idx);
+impl Crucible {
+    pub const REGULAR: Crucible = Crucible::new(1, 3);
+    pub const ULTRA: Crucible = Crucible::new(4, 10);
+
+    #[must_use]
+    pub const fn new(min_straight: usize, max_straight: usize) -> Self {
+        assert!(min_straight <= max_straight && max_straight > 0);
+        Crucible {
+            min_straight,
+            max_straight,
+        }
+    }
+
+    /// Every block the crucible can move to from `block`. One that hasn't
+    /// moved yet has to set off the way it's facing.
+    pub fn moves(self, block: Block, dims: (usize, usize)) -> impl Iterator<Item = Block> {
+        let straight = (block.count < self.max_straight).then_some(block.dir);
+        let turns = (block.count > 0 && block.count >= self.min_straight)
+            .then(|| block.dir.orthogonals())
+            .into_iter()
+            .flatten();
+        straight.into_iter().chain(turns).filter_map(move |dir| {
+            let posn = dir.next(block.posn, dims)?;
+            let count = if dir == block.dir { block.count + 1 } else { 1 };
+            Some(Block { posn, dir, count })
+        })
+    }
+}
+
+/// The heat lost entering each city block. Every block loses at least 1, so
+/// no move is free.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct City {
+    blocks: Vec<Vec<usize>>,
+}
+
+impl FromStr for City {
+    type Err = ();
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let blocks: Vec<Vec<usize>> = s
+            .lines()
+            .map(|l| {
+                l.chars()
+                    .map(|c| match c.to_digit(10) {
+                        Some(d @ 1..) => Ok(d as usize),
+                        _ => Err(()),
+                    })
+                    .collect()
+            })
+            .collect::<Result<_, _>>()?;
+        if blocks.is_empty() || blocks.iter().any(|row| row.len() != blocks[0].len()) {
+            return Err(());
+        }
+        Ok(City { blocks })
+    }
+}
+
+impl City {
+    #[must_use]
+    pub fn dims(&self) -> (usize, usize) {
+        (self.blocks.len(), self.blocks[0].len())
+    }
+
+    #[must_use]
+    pub fn heat_loss(&self, (r, c): (usize, usize)) -> usize {
+        self.blocks[r][c]
+    }
+
+    /// A search from the top left to the bottom right, setting off down or
+    /// right
+    #[must_use]
+    pub fn search(&self, crucible: Crucible) -> Search<'_> {
+        let (rows, cols) = self.dims();
+        Search {
+            city: self,
+            crucible,
+            heuristic: false,
+            start: (0, 0),
+            goal: (rows - 1, cols - 1),
+            start_dirs: &START_DIRS,
+        }
+    }
+}
+
+/// A search for the route that loses the least heat. It runs on the
+/// crucible's states directly, never building the graph.
+#[derive(Debug, Clone, Copy)]
+pub struct Search<'a> {
+    city: &'a City,
+    crucible: Crucible,
+    heuristic: bool,
+    start: (usize, usize),
+    goal: (usize, usize),
+    start_dirs: &'a [Direction],
+}
+
+impl<'a> Search<'a> {
+    /// Where the crucible sets off from. A cell outside the city means there
+    /// are no routes at all.
+    #[must_use]
+    pub fn start(mut self, start: (usize, usize)) -> Self {
+        self.start = start;
+        self
+    }
+
+    /// Where the crucible has to get to. A cell outside the city means there
+    /// are no routes at all.
+    #[must_use]
+    pub fn goal(mut self, goal: (usize, usize)) -> Self {
+        self.goal = goal;
+        self
+    }
+
+    /// The ways the crucible may set off from the start
+    #[must_use]
+    pub fn start_dirs(mut self, start_dirs: &'a [Direction]) -> Self {
+        self.start_dirs = start_dirs;
+        self
+    }
+
+    /// Whether to guide the search with A*, using the Manhattan distance to
+    /// the goal times the smallest heat loss anywhere. That never overshoots,
+    /// so the answer's still the best one.
+    #[must_use]
+    pub fn heuristic(mut self, heuristic: bool) -> Self {
+        self.heuristic = heuristic;
+        self
+    }
+
+    /// Where each state lives in the flat tables
+    fn index(&self, block: Block) -> usize {
+        let (_, cols) = self.city.dims();
+        ((block.posn.0 * cols + block.posn.1) * 4 + block.dir as usize)
+            * (self.crucible.max_straight + 1)
+            + block.count
+    }
+
+    /// Whether the crucible can stop at `block`
+    fn is_goal(&self, block: Block) -> bool {
+        block.posn == self.goal && (block.count >= self.crucible.min_straight || block.count == 0)
+    }
+
+    /// The route that loses the least heat on the way to the goal, or `None`
+    /// if there's no way to get there (or the start or goal is off the map)
+    #[must_use]
+    pub fn route(&self) -> Option<Route> {
+        self.shortest(&self.starts(), |_, _| true)
+    }
+
+    /// The states the crucible can be in before it's moved. There aren't any
+    /// if the start or goal is off the map.
+    fn starts(&self) -> Vec<Block> {
+        let (rows, cols) = self.city.dims();
+        let on_map = |(r, c): (usize, usize)| r < rows && c < cols;
+        if !on_map(self.start) || !on_map(self.goal) {
+            return vec![];
+        }
+        self.start_dirs
+            .iter()
+            .map(|&dir| Block {
+                posn: self.start,
+                dir,
+                count: 0,
+            })
+            .collect()
+    }
+
+    /// The cheapest route from any of `starts` to the goal that only makes
+    /// the moves `allowed` lets it
+    fn shortest<F>(&self, starts: &[Block], allowed: F) -> Option<Route>
+    where
+        F: Fn(Block, Block) -> bool,
+    {
+        let dims = self.city.dims();
+        let goal = self.goal;
+        let cheapest = self.city.blocks.iter().flatten().copied().min().unwrap();
+        let estimate = |posn: (usize, usize)| {
+            if self.heuristic {
+                (posn.0.abs_diff(goal.0) + posn.1.abs_diff(goal.1)) * cheapest
+            } else {
+                0
+            }
+        };
+
+        let states = dims.0 * dims.1 * 4 * (self.crucible.max_straight + 1);
+        let mut best = vec![usize::MAX; states];
+        let mut came_from = vec![None; states];
+        let mut heap = BinaryHeap::new();
+        for &start in starts {
+            best[self.index(start)] = 0;
+            heap.push(Reverse((estimate(start.posn), 0, start)));
+        }
+        while let Some(Reverse((_, cost, block))) = heap.pop() {
+            if cost > best[self.index(block)] {
+                continue;
+            }
+            if self.is_goal(block) {
+                let mut steps = vec![block];
+                while let Some(prev) = came_from[self.index(steps[steps.len() - 1])] {
+                    steps.push(prev);
+                }
+                steps.reverse();
+                return Some(Route {
+                    heat_loss: cost,
+                    steps,
+                });
+            }
+            for next in self.crucible.moves(block, dims) {
+                if !allowed(block, next) {
+                    continue;
+                }
+                let next_cost = cost + self.city.heat_loss(next.posn);
+                let i = self.index(next);
+                if next_cost < best[i] {
+                    best[i] = next_cost;
+                    came_from[i] = Some(block);
+                    heap.push(Reverse((next_cost + estimate(next.posn), next_cost, next)));
                 }
-                col.insert(*d, stack);
             }
-            row.push(col);
         }
-        nodes.push(row);
+        None
     }
-    let nodes = nodes;
-    let dims = (blocks.len(), blocks[0].len());
-
-    // Now go through to make edges
-    for r in 0..blocks.len() {
-        for c in 0..blocks[r].len() {
-            for d in &DIRS {
-                for i in 1..T1_MAX_STRAIGHT {
-                    // I'm not at the top. I can connect to i + 1,
-                    // with the weight of the NEXT one in this same direction...
-                    // at least, if I'm not at the edge
-                    if let Some((nr, nc)) = d.next((r, c), dims) {
-                        // wt is how much it costs to get to the next one.
-                        let wt = blocks[nr][nc];
-                        graph.add_edge(nodes[r][c][d][i - 1], nodes[nr][nc][d][i], wt);
-                    }
-                    // I can always go the orthogonal directions...
-                    for ortho in d.orthogonals() {
-                        // ... or can I? Check!
-                        if let Some((nr, nc)) = ortho.next((r, c), dims) {
-                            let wt = blocks[nr][nc];
-                            graph.add_edge(nodes[r][c][d][i - 1], nodes[nr][nc][&ortho][0], wt);
-                        }
-                    }
+
+    /// The `k` routes that lose the least heat, best first, by Yen's
+    /// algorithm. Each new route branches off one already found: it follows
+    /// it up to some state, then takes the cheapest way on from there that
+    /// no route with the same beginning has taken, and never goes back
+    /// through a state it's already been in.
+    #[must_use]
+    pub fn routes(&self, k: usize) -> Vec<Route> {
+        let mut found: Vec<Route> = vec![];
+        let mut candidates = BinaryHeap::new();
+        let mut seen = HashSet::new();
+        if let Some(route) = self.route() {
+            seen.insert(route.steps.clone());
+            candidates.push(Reverse((route.heat_loss, route.steps)));
+        }
+        while found.len() < k {
+            let Some(Reverse((heat_loss, steps))) = candidates.pop() else {
+                break;
+            };
+            found.push(Route { heat_loss, steps });
+            let last = &found[found.len() - 1];
+
+            // Branching off before the first step means starting out another
+            // way
+            let unused: Vec<_> = self
+                .starts()
+                .into_iter()
+                .filter(|&s| found.iter().all(|r| r.steps[0] != s))
+                .collect();
+            let mut spurs: Vec<_> = self.shortest(&unused, |_, _| true).into_iter().collect();
+
+            let mut root_loss = 0;
+            for i in 0..last.steps.len() - 1 {
+                if i > 0 {
+                    root_loss += self.city.heat_loss(last.steps[i].posn);
                 }
-                for ortho in d.orthogonals() {
-                    // ... or can I? Check!
-                    if let Some((nr, nc)) = ortho.next((r, c), dims) {
-                        let wt = blocks[nr][nc];
-                        graph.add_edge(
-                            nodes[r][c][d].last().copied().unwrap(),
-                            nodes[nr][nc][&ortho][0],
-                            wt,
-                        );
-                    }
+                let root = &last.steps[..=i];
+                let taken: HashSet<_> = found
+                    .iter()
+                    .filter(|r| r.steps.len() > i + 1 && r.steps[..=i] == *root)
+                    .map(|r| r.steps[i + 1])
+                    .collect();
+                let visited: HashSet<_> = root.iter().copied().collect();
+                let spur = self.shortest(&[root[i]], |from, to| {
+                    !(visited.contains(&to) || from == root[i] && taken.contains(&to))
+                });
+                spurs.extend(spur.map(|spur| Route {
+                    heat_loss: root_loss + spur.heat_loss,
+                    steps: root[..i].iter().chain(&spur.steps).copied().collect(),
+                }));
+            }
+            for route in spurs {
+                if seen.insert(route.steps.clone()) {
+                    candidates.push(Reverse((route.heat_loss, route.steps)));
                 }
             }
         }
+        found
     }
-    let graph = graph;
 
-    let end_nodes: HashSet<_> = nodes
-        .last()
-        .unwrap()
-        .last()
-        .unwrap()
-        .values()
-        .flatten()
-        .copied()
-        .collect();
+    /// How many different routes lose the least heat
+    #[must_use]
+    pub fn count_optimal(&self) -> u128 {
+        let dims = self.city.dims();
+        let states = dims.0 * dims.1 * 4 * (self.crucible.max_straight + 1);
+        let mut best = vec![usize::MAX; states];
+        let mut ways = vec![0u128; states];
+        let mut heap = BinaryHeap::new();
+        for start in self.starts() {
+            best[self.index(start)] = 0;
+            ways[self.index(start)] = 1;
+            heap.push(Reverse((0, start)));
+        }
+        let mut optimum = None;
+        let mut total = 0;
+        while let Some(Reverse((cost, block))) = heap.pop() {
+            if cost > best[self.index(block)] || optimum.is_some_and(|o| cost > o) {
+                continue;
+            }
+            if self.is_goal(block) {
+                optimum = Some(cost);
+                total += ways[self.index(block)];
+                continue;
+            }
+            for next in self.crucible.moves(block, dims) {
+                let next_cost = cost + self.city.heat_loss(next.posn);
+                let (i, j) = (self.index(block), self.index(next));
+                if next_cost < best[j] {
+                    best[j] = next_cost;
+                    ways[j] = ways[i];
+                    heap.push(Reverse((next_cost, next)));
+                } else if next_cost == best[j] {
+                    ways[j] += ways[i];
+                }
+            }
+        }
+        total
+    }
 
-    nodes
-        .first()
-        .unwrap()
-        .first()
-        .unwrap()
-        .iter()
-        .filter_map(|(d, idx)| {
-            if START_DIRS.contains(d) {
-                Some(idx[0])
-            } else {
-                None
+    /// The least heat the crucible can lose on the way to the goal, or `None`
+    /// if there's no way to get there
+    #[must_use]
+    pub fn min_heat_loss(&self) -> Option<usize> {
+        self.route().map(|route| route.heat_loss)
+    }
+}
+
+/// A crucible's way through the city. The first step is where it starts,
+/// before it's moved.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Route {
+    pub heat_loss: usize,
+    pub steps: Vec<Block>,
+}
+
+impl Route {
+    pub fn positions(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.steps.iter().map(|b| b.posn)
+    }
+
+    /// Which way the crucible went on each move
+    pub fn moves(&self) -> impl Iterator<Item = Direction> + '_ {
+        self.steps.iter().skip(1).map(|b| b.dir)
+    }
+
+    /// Draws the route over the city like the puzzle does, with an arrow on
+    /// every block the crucible moves into
+    #[must_use]
+    pub fn view<'a>(&'a self, city: &'a City) -> RouteView<'a> {
+        RouteView { route: self, city }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RouteView<'a> {
+    route: &'a Route,
+    city: &'a City,
+}
+
+impl std::fmt::Display for RouteView<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let arrows: HashMap<_, _> = self
+            .route
+            .steps
+            .iter()
+            .skip(1)
+            .map(|b| (b.posn, b.dir.arrow()))
+            .collect();
+        for (r, row) in self.city.blocks.iter().enumerate() {
+            for (c, heat) in row.iter().enumerate() {
+                match arrows.get(&(r, c)) {
+                    Some(arrow) => write!(f, "{arrow}")?,
+                    None => write!(f, "{heat}")?,
+                }
             }
-        })
-        .flat_map(|n| {
-            dijkstra(&graph, n, None, |e| *e.weight())
-                .into_iter()
-                .filter_map(|(n_idx, cost)| {
-                    if end_nodes.contains(&n_idx) {
-                        Some(cost)
-                    } else {
-                        None
-                    }
-                })
-        })
-        .min()
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+#[aoc_generator(day17)]
+pub fn gen(input: &str) -> City {
+    input.parse().unwrap()
+}
+
+#[aoc(day17, part1)]
+pub fn part1(city: &City) -> usize {
+    city.search(Crucible::REGULAR)
+        .heuristic(true)
+        .min_heat_loss()
         .unwrap()
 }
 
 #[aoc(day17, part2)]
-pub fn part2(input: &str) -> usize {
-    let blocks: Vec<Vec<usize>> = input
-        .lines()
-        .map(|l| l.chars().map(|c| c.to_string().parse().unwrap()).collect())
-        .collect();
-    let mut graph = Graph::<_, _>::new();
-    let mut nodes = Vec::with_capacity(blocks.len());
-    for (r, block_row) in blocks.iter().enumerate() {
-        let mut row = Vec::with_capacity(blocks[r].len());
-        for c in 0..block_row.len() {
-            let mut col = HashMap::with_capacity(DIRS.len());
-            for d in &DIRS {
-                let mut stack = Vec::with_capacity(3);
-                for i in 1..=10 {
-                    let idx = graph.add_node(Block {
-                        count: i,
-                        dir: *d,
-                        posn: (r, c),
-                    });
-                    stack.push(idx);
+pub fn part2(city: &City) -> usize {
+    city.search(Crucible::ULTRA)
+        .heuristic(true)
+        .min_heat_loss()
+        .unwrap()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{gen, part1, part2, Block, City, Crucible, Direction};
+    use std::collections::HashSet;
+
+    const EXAMPLE: &str = "2413432311323
+3215453535623
+3255245654254
+3446585845452
+4546657867536
+1438598798454
+4457876987766
+3637877979653
+4654967986887
+4564679986453
+1224686865563
+2546548887735
+4322674655533";
+
+    /// The heat lost along every route from the top left to the bottom
+    /// right that never repeats a state, cheapest first
+    fn brute_force(city: &City, crucible: Crucible) -> Vec<usize> {
+        fn dfs(
+            block: Block,
+            loss: usize,
+            path: &mut HashSet<Block>,
+            city: &City,
+            crucible: Crucible,
+            losses: &mut Vec<usize>,
+        ) {
+            let (rows, cols) = city.dims();
+            if block.posn == (rows - 1, cols - 1) && block.count >= crucible.min_straight {
+                losses.push(loss);
+                return;
+            }
+            for next in crucible.moves(block, city.dims()) {
+                if path.insert(next) {
+                    let loss = loss + city.heat_loss(next.posn);
+                    dfs(next, loss, path, city, crucible, losses);
+                    path.remove(&next);
                 }
-                col.insert(*d, stack);
             }
-            row.push(col);
         }
-        nodes.push(row);
+
+        let mut losses = vec![];
+        for dir in [Direction::Down, Direction::Right] {
+            let start = Block {
+                posn: (0, 0),
+                dir,
+                count: 0,
+            };
+            dfs(
+                start,
+                0,
+                &mut HashSet::from([start]),
+                city,
+                crucible,
+                &mut losses,
+            );
+        }
+        losses.sort_unstable();
+        losses
     }
 
-    let dims = (blocks.len(), blocks[0].len());
-
-    // Now go through to make edges
-    for r in 0..blocks.len() {
-        for c in 0..blocks[r].len() {
-            for d in &DIRS {
-                for i in 1..T2_MIN_STRAIGHT {
-                    // HAVE to go in the same direction!
-                    if let Some((nr, nc)) = d.next((r, c), dims) {
-                        let wt = blocks[nr][nc];
-                        graph.add_edge(nodes[r][c][d][i - 1], nodes[nr][nc][d][i], wt);
-                    }
-                }
-                for i in T2_MIN_STRAIGHT..T2_MAX_STRAIGHT {
-                    // I'm not at the top. I can connect to i + 1,
-                    // with the weight of the NEXT one in this same direction...
-                    // at least, if I'm not at the edge
-                    if let Some((nr, nc)) = d.next((r, c), dims) {
-                        // wt is how much it costs to get to the next one.
-                        let wt = blocks[nr][nc];
-                        graph.add_edge(nodes[r][c][d][i - 1], nodes[nr][nc][d][i], wt);
-                    }
-                    // I can always go the orthogonal directions...
-                    for ortho in d.orthogonals() {
-                        // ... or can I? Check!
-                        if let Some((nr, nc)) = ortho.next((r, c), dims) {
-                            let wt = blocks[nr][nc];
-                            graph.add_edge(nodes[r][c][d][i - 1], nodes[nr][nc][&ortho][0], wt);
-                        }
-                    }
-                }
-                for ortho in d.orthogonals() {
-                    // ... or can I? Check!
-                    if let Some((nr, nc)) = ortho.next((r, c), dims) {
-                        let wt = blocks[nr][nc];
-                        graph.add_edge(
-                            nodes[r][c][d].last().copied().unwrap(),
-                            nodes[nr][nc][&ortho][0],
-                            wt,
-                        );
-                    }
-                }
-            }
+    #[test]
+    pub fn example() {
+        let city = gen(EXAMPLE);
+        assert_eq!(part1(&city), 102);
+        assert_eq!(part2(&city), 94);
+        assert_eq!(
+            part2(&gen(
+                "111111111111\n999999999991\n999999999991\n999999999991\n999999999991"
+            )),
+            71
+        );
+        assert!("19\n91".parse::<City>().is_ok());
+        assert!("10\n01".parse::<City>().is_err());
+        assert!("1a\n11".parse::<City>().is_err());
+        assert!("11\n1".parse::<City>().is_err());
+        assert!("".parse::<City>().is_err());
+    }
+
+    #[test]
+    pub fn routes() {
+        let city = gen(EXAMPLE);
+        let search = city.search(Crucible::REGULAR).heuristic(true);
+        let routes = search.routes(10);
+        assert_eq!(routes.len(), 10);
+        assert_eq!(routes[0].heat_loss, 102);
+        assert!(routes.windows(2).all(|w| w[0].heat_loss <= w[1].heat_loss));
+        let distinct: HashSet<_> = routes.iter().map(|r| &r.steps).collect();
+        assert_eq!(distinct.len(), routes.len());
+        for route in &routes {
+            let loss: usize = route.positions().skip(1).map(|p| city.heat_loss(p)).sum();
+            assert_eq!(loss, route.heat_loss);
+        }
+        let optimal = routes.iter().filter(|r| r.heat_loss == 102).count();
+        assert_eq!(search.count_optimal(), optimal as u128);
+    }
+
+    #[test]
+    pub fn routes_brute_force() {
+        for (input, crucible) in [
+            ("131\n221\n312", Crucible::new(1, 2)),
+            ("21\n12\n31\n12", Crucible::new(1, 3)),
+            ("1111\n1111", Crucible::new(1, 2)),
+            ("2131\n1212\n3121", Crucible::new(2, 3)),
+        ] {
+            let city = gen(input);
+            let expected = brute_force(&city, crucible);
+            let search = city.search(crucible);
+            let found: Vec<_> = search
+                .routes(expected.len() + 1)
+                .iter()
+                .map(|r| r.heat_loss)
+                .collect();
+            assert_eq!(found, expected, "{input}");
+            let optimal = expected.iter().filter(|&&l| l == expected[0]).count();
+            assert_eq!(search.count_optimal(), optimal as u128, "{input}");
         }
     }
-    let graph = graph;
-    let end_nodes: HashSet<_> = nodes[nodes.len() - 1][nodes.len() - 1]
-        .values()
-        .flatten()
-        .copied()
-        .collect();
-
-    nodes[0][0]
-        .iter()
-        .filter_map(|(d, idx)| {
-            if START_DIRS.contains(d) {
-                Some(idx[0])
-            } else {
-                None
-            }
-        })
-        .flat_map(|n| {
-            dijkstra(&graph, n, None, |e| *e.weight())
-                .into_iter()
-                .filter_map(|(n_idx, cost)| {
-                    if end_nodes.contains(&n_idx) {
-                        Some(cost)
-                    } else {
-                        None
-                    }
-                })
-        })
-        .min()
-        .unwrap()
 }
@@ -1,9 +1,7 @@
 use aoc_runner_derive::{aoc, aoc_generator};
+use gcd::Gcd;
 use std::str::FromStr;
 
-use crate::day10::{Grid, Tile};
-
-use itertools::Itertools;
 use regex::Regex;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -45,19 +43,15 @@ pub struct PlanRecord {
 }
 
 impl PlanRecord {
-    fn explode(&self) -> impl Iterator<Item = Direction> + '_ {
-        std::iter::repeat(self.dir).take(self.len)
-    }
-}
-
-impl std::ops::Add<&PlanRecord> for (isize, isize) {
-    type Output = (isize, isize);
-    fn add(self, rhs: &PlanRecord) -> Self::Output {
-        match rhs.dir {
-            Direction::Down => (self.0 + rhs.len as isize, self.1),
-            Direction::Left => (self.0, self.1 - rhs.len as isize),
-            Direction::Right => (self.0, self.1 + rhs.len as isize),
-            Direction::Up => (self.0 - rhs.len as isize, self.1),
+    /// The instruction hidden in the color: the first five hex digits are
+    /// the length, and the last is the direction
+    #[must_use]
+    pub fn decoded(&self) -> PlanRecord {
+        let (len, dir) = self.color.split_at(5);
+        PlanRecord {
+            dir: dir.chars().next().unwrap().into(),
+            len: usize::from_str_radix(len, 16).unwrap(),
+            color: self.color.clone(),
         }
     }
 }
@@ -75,65 +69,170 @@ impl FromStr for PlanRecord {
     }
 }
 
-impl std::ops::Add<Direction> for (isize, isize) {
-    type Output = (isize, isize);
-    fn add(self, rhs: Direction) -> Self::Output {
-        match rhs {
-            Direction::Down => (self.0 + 1, self.1),
-            Direction::Left => (self.0, self.1 - 1),
-            Direction::Right => (self.0, self.1 + 1),
-            Direction::Up => (self.0 - 1, self.1),
+impl std::ops::Add<&PlanRecord> for (i128, i128) {
+    type Output = (i128, i128);
+    fn add(self, rhs: &PlanRecord) -> Self::Output {
+        let len = rhs.len as i128;
+        match rhs.dir {
+            Direction::Down => (self.0 + len, self.1),
+            Direction::Left => (self.0, self.1 - len),
+            Direction::Right => (self.0, self.1 + len),
+            Direction::Up => (self.0 - len, self.1),
         }
     }
 }
 
-impl std::ops::Add<Direction> for Direction {
-    type Output = Tile;
-    fn add(self, rhs: Direction) -> Self::Output {
-        (self, rhs).into()
+/// The cross product of `b - a` and `c - a`: positive if `a`, `b`, `c` turn
+/// counterclockwise, negative if clockwise, and zero if they're in a line
+fn orientation(a: (i128, i128), b: (i128, i128), c: (i128, i128)) -> i128 {
+    (b.0 - a.0) * (c.1 - a.1) - (b.1 - a.1) * (c.0 - a.0)
+}
+
+/// Whether `p`, known to be in line with `a` and `b`, is between them
+fn within(a: (i128, i128), b: (i128, i128), p: (i128, i128)) -> bool {
+    a.0.min(b.0) <= p.0 && p.0 <= a.0.max(b.0) && a.1.min(b.1) <= p.1 && p.1 <= a.1.max(b.1)
+}
+
+/// How far a segment goes on each axis
+fn delta((from, to): ((i128, i128), (i128, i128))) -> (i128, i128) {
+    (to.0 - from.0, to.1 - from.1)
+}
+
+/// Whether two segments share any point, ends included
+fn segments_touch(a: ((i128, i128), (i128, i128)), b: ((i128, i128), (i128, i128))) -> bool {
+    let (o1, o2) = (orientation(a.0, a.1, b.0), orientation(a.0, a.1, b.1));
+    let (o3, o4) = (orientation(b.0, b.1, a.0), orientation(b.0, b.1, a.1));
+    if o1.signum() * o2.signum() < 0 && o3.signum() * o4.signum() < 0 {
+        return true;
     }
+    (o1 == 0 && within(a.0, a.1, b.0))
+        || (o2 == 0 && within(a.0, a.1, b.1))
+        || (o3 == 0 && within(b.0, b.1, a.0))
+        || (o4 == 0 && within(b.0, b.1, a.1))
 }
 
-impl From<(Direction, Direction)> for Tile {
-    fn from(value: (Direction, Direction)) -> Self {
-        match value {
-            (Direction::Up | Direction::Down, Direction::Up | Direction::Down) => Tile::NS,
-            (Direction::Left | Direction::Right, Direction::Left | Direction::Right) => Tile::EW,
-            (Direction::Up, Direction::Left) | (Direction::Right, Direction::Down) => Tile::SW,
-            (Direction::Up, Direction::Right) | (Direction::Left, Direction::Down) => Tile::SE,
-            (Direction::Down, Direction::Left) | (Direction::Right, Direction::Up) => Tile::NW,
-            (Direction::Down, Direction::Right) | (Direction::Left, Direction::Up) => Tile::NE,
+/// The trench a dig plan makes, as the corners it turns at. Everything is
+/// exact integer arithmetic, so there's no grid to fill in no matter how big
+/// the lagoon gets.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Polygon {
+    vertices: Vec<(i128, i128)>,
+}
+
+impl FromIterator<(i128, i128)> for Polygon {
+    fn from_iter<T: IntoIterator<Item = (i128, i128)>>(iter: T) -> Self {
+        Polygon {
+            vertices: iter.into_iter().collect(),
         }
     }
 }
 
-fn dig(plan: &[PlanRecord]) -> (Grid, usize) {
-    let mut trench = vec![];
-    let mut posn = (0isize, 0isize);
-    for (curr, next) in plan.iter().flat_map(PlanRecord::explode).tuple_windows() {
-        posn = posn + curr;
-        trench.push((posn, curr + next));
+impl<'a> FromIterator<&'a PlanRecord> for Polygon {
+    /// Digs from `(0, 0)`, which is also where the plan should end up
+    fn from_iter<T: IntoIterator<Item = &'a PlanRecord>>(iter: T) -> Self {
+        iter.into_iter()
+            .scan((0, 0), |posn, plan| {
+                *posn = *posn + plan;
+                Some(*posn)
+            })
+            .collect()
     }
-    let last = plan.last().unwrap().dir + plan.first().unwrap().dir;
-    trench.push(((0, 0), last));
-    let base_row = *trench.iter().map(|((r, _), _)| r).min().unwrap();
-    let base_col = *trench.iter().map(|((_, c), _)| c).min().unwrap();
-    let trench_len = trench.len();
-    let augmented = trench
-        .into_iter()
-        .map(|((r, c), t)| (((r - base_row) as usize, (c - base_col) as usize), t));
-    (Grid::from(augmented), trench_len)
 }
 
-fn vertices(plans: &[PlanRecord]) -> Vec<(isize, isize)> {
-    // Start at 0, 0
-    let mut point = (0isize, 0isize);
-    let mut out = vec![];
-    for plan in plans {
-        point = point + plan;
-        out.push(point);
+impl Polygon {
+    #[must_use]
+    pub fn vertices(&self) -> &[(i128, i128)] {
+        &self.vertices
+    }
+
+    /// Every edge, including the one from the last vertex back to the first
+    pub fn edges(&self) -> impl Iterator<Item = ((i128, i128), (i128, i128))> + '_ {
+        let n = self.vertices.len();
+        (0..n).map(move |i| (self.vertices[i], self.vertices[(i + 1) % n]))
+    }
+
+    /// Twice the area, by the shoelace formula
+    #[must_use]
+    pub fn double_area(&self) -> i128 {
+        self.edges()
+            .map(|(a, b)| a.0 * b.1 - b.0 * a.1)
+            .sum::<i128>()
+            .abs()
+    }
+
+    /// How many lattice points sit on the boundary. Each edge has as many as
+    /// the gcd of how far it goes on each axis; for a dig plan, that's just
+    /// its length.
+    #[must_use]
+    pub fn boundary(&self) -> i128 {
+        self.edges()
+            .map(|(a, b)| {
+                let (dr, dc) = ((b.0 - a.0).unsigned_abs(), (b.1 - a.1).unsigned_abs());
+                dr.gcd(dc) as i128
+            })
+            .sum()
+    }
+
+    /// How many lattice points are strictly inside, by Pick's theorem:
+    /// A = I + B/2 - 1, so I = (2A - B + 2) / 2. This only means anything if
+    /// the polygon's simple.
+    #[must_use]
+    pub fn interior(&self) -> i128 {
+        (self.double_area() - self.boundary() + 2) / 2
+    }
+
+    /// How many cubic meters the lagoon holds: the trench plus everything
+    /// inside it
+    #[must_use]
+    pub fn lagoon(&self) -> i128 {
+        self.interior() + self.boundary()
+    }
+
+    /// The top left and bottom right corners of the smallest box that holds
+    /// the polygon, or `None` if there are no vertices
+    #[must_use]
+    pub fn bounding_box(&self) -> Option<((i128, i128), (i128, i128))> {
+        let (first, rest) = self.vertices.split_first()?;
+        Some(rest.iter().fold((*first, *first), |(lo, hi), &(r, c)| {
+            ((lo.0.min(r), lo.1.min(c)), (hi.0.max(r), hi.1.max(c)))
+        }))
+    }
+
+    /// Every pair of edges `(i, j)`, `i < j`, that cross or touch when they
+    /// shouldn't. Neighboring edges are allowed to share their corner, but
+    /// not to double back over each other. Zero-length edges are skipped, so
+    /// the edges on either side of one count as neighbors.
+    #[must_use]
+    pub fn self_intersections(&self) -> Vec<(usize, usize)> {
+        let edges: Vec<_> = self
+            .edges()
+            .enumerate()
+            .filter(|&(_, e)| delta(e) != (0, 0))
+            .collect();
+        let n = edges.len();
+        let mut found = vec![];
+        for i in 0..n {
+            for j in (i + 1)..n {
+                let ((ei, a), (ej, b)) = (edges[i], edges[j]);
+                let touching = if j == i + 1 || (i == 0 && j == n - 1) {
+                    // They meet at a corner, so only overlapping counts
+                    let (da, db) = (delta(a), delta(b));
+                    da.0 * db.1 == da.1 * db.0 && da.0 * db.0 + da.1 * db.1 < 0
+                } else {
+                    segments_touch(a, b)
+                };
+                if touching {
+                    found.push((ei, ej));
+                }
+            }
+        }
+        found
+    }
+
+    #[must_use]
+    pub fn is_simple(&self) -> bool {
+        self.self_intersections().is_empty()
     }
-    out
 }
 
 #[aoc_generator(day18)]
@@ -142,35 +241,118 @@ pub fn gen(input: &str) -> Vec<PlanRecord> {
 }
 
 #[aoc(day18, part1)]
-pub fn part1(plans: &[PlanRecord]) -> usize {
-    let (dig, trench) = dig(plans);
-    dig.num_contained() + trench
+pub fn part1(plans: &[PlanRecord]) -> i128 {
+    plans.iter().collect::<Polygon>().lagoon()
 }
 
 #[aoc(day18, part2)]
-pub fn part2(plans: &[PlanRecord]) -> isize {
-    let verts = vertices(plans);
-
-    let area = verts
-        .windows(2)
-        .map(|vv| (vv[0], vv[1]))
-        // Connect back to where we started
-        .chain(std::iter::once((
-            *verts.last().unwrap(),
-            *verts.first().unwrap(),
-        )))
-        .map(|(v1, v2)| (v1.1 - v2.1) * v1.0)
-        .sum::<isize>();
-    let perim = verts
-        .windows(2)
-        .map(|vv| (vv[0], vv[1]))
-        // Connect back to where we started
-        .chain(std::iter::once((
-            *verts.last().unwrap(),
-            *verts.first().unwrap(),
-        )))
-        .map(|(v1, v2)| (v1.1.abs_diff(v2.1) + v1.0.abs_diff(v2.0)) as isize)
-        .sum::<isize>();
-    // We've only added half the perimeter! And we haven't included the top left square
-    area + perim / 2 + 1
+pub fn part2(plans: &[PlanRecord]) -> i128 {
+    let decoded: Vec<_> = plans.iter().map(PlanRecord::decoded).collect();
+    decoded.iter().collect::<Polygon>().lagoon()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{gen, part1, part2, Polygon};
+
+    const EXAMPLE: &str = "R 6 (#70c710)
+D 5 (#0dc571)
+L 2 (#5713f0)
+D 2 (#d2c081)
+R 2 (#59c680)
+D 2 (#411b91)
+L 5 (#8ceee2)
+U 2 (#caa173)
+L 1 (#1b58a2)
+U 2 (#caa171)
+R 2 (#7807d2)
+U 3 (#a77fa3)
+L 2 (#015232)
+U 2 (#7a21e3)";
+
+    #[test]
+    pub fn examples() {
+        let plans = gen(EXAMPLE);
+        assert_eq!(part1(&plans), 62);
+        assert_eq!(part2(&plans), 952408144115);
+    }
+
+    #[test]
+    pub fn decoded() {
+        let plans = gen(EXAMPLE);
+        assert_eq!(plans[0].decoded(), gen("R 461937 (#70c710)")[0]);
+        assert_eq!(plans[1].decoded(), gen("D 56407 (#0dc571)")[0]);
+        assert_eq!(plans[13].decoded(), gen("U 500254 (#7a21e3)")[0]);
+    }
+
+    /// A polygon from moves like `"R 2, D 1"`, with the colors left out
+    fn dig(moves: &str) -> Polygon {
+        let plan: Vec<_> = moves
+            .split(", ")
+            .map(|m| format!("{m} (#000000)"))
+            .collect();
+        gen(&plan.join("\n")).iter().collect()
+    }
+
+    #[test]
+    pub fn zero_length_edges() {
+        let square = dig("R 2, D 0, D 2, L 2, U 2");
+        assert_eq!(square.self_intersections(), []);
+        assert_eq!(square.lagoon(), 9);
+
+        // Doubling back is still caught when there's nothing between the turns
+        let doubled = dig("R 2, U 0, L 1, D 1, L 1, U 1");
+        assert!(doubled.self_intersections().contains(&(1, 5)));
+    }
+
+    #[test]
+    pub fn measurements() {
+        let lagoon: Polygon = gen(EXAMPLE).iter().collect();
+        assert_eq!(lagoon.boundary(), 38);
+        assert_eq!(lagoon.double_area(), 84);
+        assert_eq!(lagoon.interior(), 24);
+        assert_eq!(lagoon.bounding_box(), Some(((0, 0), (9, 6))));
+
+        // Slanted edges only pass through some lattice points
+        let triangle: Polygon = [(0, 0), (0, 4), (2, 0)].into_iter().collect();
+        assert_eq!(triangle.boundary(), 8);
+        assert_eq!(triangle.double_area(), 8);
+        assert_eq!(triangle.interior(), 1);
+        assert_eq!(triangle.bounding_box(), Some(((0, 0), (2, 4))));
+
+        let up_left = dig("U 3, L 5, D 3, R 5");
+        assert_eq!(up_left.bounding_box(), Some(((-3, -5), (0, 0))));
+        assert_eq!(up_left.lagoon(), 24);
+
+        let empty: Polygon = std::iter::empty::<(i128, i128)>().collect();
+        assert_eq!(empty.bounding_box(), None);
+        assert_eq!(empty.boundary(), 0);
+        assert!(empty.is_simple());
+    }
+
+    #[test]
+    pub fn self_intersections() {
+        assert!(gen(EXAMPLE).iter().collect::<Polygon>().is_simple());
+
+        let bowtie: Polygon = [(0, 0), (2, 2), (2, 0), (0, 2)].into_iter().collect();
+        assert_eq!(bowtie.self_intersections(), [(0, 2)]);
+
+        // Coming back to touch the first edge in the middle, then running
+        // back along it
+        let touching: Polygon = [(0, 0), (0, 4), (2, 4), (2, 2), (0, 2)]
+            .into_iter()
+            .collect();
+        assert_eq!(touching.self_intersections(), [(0, 3), (0, 4)]);
+
+        // Neighbors sharing a corner at a right angle or in a straight line
+        // are fine
+        assert!(dig("R 1, R 1, D 1, L 2, U 1").is_simple());
+
+        // Edges start from the first corner dug, so the first move is the
+        // last edge
+        assert_eq!(
+            dig("R 2, L 1, D 1, L 1, U 1").self_intersections(),
+            [(0, 4), (1, 4)]
+        );
+    }
 }
@@ -85,6 +85,7 @@ fn in_range(xy: &(f64, f64)) -> bool {
     rng.contains(&x) && rng.contains(&y)
 }
 
+#[allow(clippy::needless_range_loop)]
 fn rref<const M: usize, const N: usize>(mut matrix: [[f64; N]; M]) -> [f64; M] {
     let mut h = 0;
     let mut k = 0;
@@ -5,102 +5,145 @@ use std::collections::HashMap;
 use gcd::{self, Gcd};
 use regex::Regex;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
-enum Direction {
-    Left,
-    Right,
+/// The classic puzzle alphabet: `L` picks the first successor, `R` the second
+pub const LEFT_RIGHT: &str = "LR";
+
+/// A network of labelled nodes, each with an ordered list of successors. Each
+/// instruction is an index into that list, so `L`/`R` networks are just the
+/// two-successor case.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Network<'a> {
+    instructions: Vec<usize>,
+    nodes: HashMap<&'a str, Vec<&'a str>>,
 }
 
-impl From<char> for Direction {
-    fn from(value: char) -> Self {
-        match value {
-            'L' => Self::Left,
-            'R' => Self::Right,
-            _ => unreachable!(),
+impl<'a> Network<'a> {
+    /// Parses a network whose instructions are the characters of `alphabet`;
+    /// the n-th character of the alphabet selects the n-th successor. Returns
+    /// `None` if there are no instructions, an instruction isn't in the
+    /// alphabet, a line is malformed, a node is defined twice, some node has
+    /// fewer successors than the instructions could ask for, or a successor is
+    /// never defined.
+    #[must_use]
+    pub fn with_alphabet(input: &'a str, alphabet: &str) -> Option<Self> {
+        let mut lines = input.lines();
+        let instructions: Vec<usize> = lines
+            .next()?
+            .chars()
+            .map(|c| alphabet.chars().position(|a| a == c))
+            .collect::<Option<_>>()?;
+        if instructions.is_empty() {
+            return None;
+        }
+        let reg = Regex::new(r"^(\w+) = \((\w+(?:, \w+)*)\)$").unwrap();
+        let mut nodes = HashMap::new();
+        for l in lines.skip(1) {
+            let m = reg.captures(l)?;
+            let successors = m.get(2).unwrap().as_str().split(", ").collect::<Vec<_>>();
+            if nodes
+                .insert(m.get(1).unwrap().as_str(), successors)
+                .is_some()
+            {
+                return None;
+            }
+        }
+        let arity = instructions.iter().max().map_or(0, |&m| m + 1);
+        if nodes
+            .values()
+            .any(|succ| succ.len() < arity || succ.iter().any(|s| !nodes.contains_key(s)))
+        {
+            return None;
         }
+        Some(Network {
+            instructions,
+            nodes,
+        })
     }
-}
 
-fn traverse_single(dirs: &[Direction], map: &HashMap<&str, (&str, &str)>) -> usize {
-    let mut seed = "AAA";
-    let mut counter: usize = 0;
-    for dir in dirs.iter().cycle() {
-        let choices = map[seed];
-        seed = match dir {
-            Direction::Left => choices.0,
-            Direction::Right => choices.1,
-        };
-        counter += 1;
-        if seed == "ZZZ" {
-            break;
-        }
+    pub fn labels(&self) -> impl Iterator<Item = &'a str> + '_ {
+        self.nodes.keys().copied()
     }
-    counter
-}
 
-// Naive...
-fn traverse(start: &str, dirs: &[Direction], map: &HashMap<&str, (&str, &str)>) -> usize {
-    let mut seed = start;
-    let mut counter: usize = 0;
-    for dir in dirs.iter().cycle() {
-        let choices = map[seed];
-        seed = match dir {
-            Direction::Left => choices.0,
-            Direction::Right => choices.1,
-        };
-        counter += 1;
-        if seed.ends_with('Z') {
-            break;
+    /// Follows the instructions from `start` until `is_end` holds, returning
+    /// the number of steps taken. At least one step is always taken. Returns
+    /// `None` if `start` isn't a node.
+    pub fn steps<F>(&self, start: &str, is_end: F) -> Option<usize>
+    where
+        F: Fn(&str) -> bool,
+    {
+        let mut seed = *self.nodes.get_key_value(start)?.0;
+        let mut counter: usize = 0;
+        for &inst in self.instructions.iter().cycle() {
+            seed = self.nodes[seed][inst];
+            counter += 1;
+            if is_end(seed) {
+                break;
+            }
         }
+        Some(counter)
     }
-    counter
-}
 
-fn traverse_ghost(dirs: &[Direction], map: &HashMap<&str, (&str, &str)>) -> usize {
-    let mut times = map
-        .keys()
-        .filter(|k| k.ends_with('A'))
-        .map(|k| traverse(k, dirs, map));
-
-    let mut ans = times.next().unwrap();
-    for t in times {
-        ans = t * ans / t.gcd(ans);
+    // Naive... this assumes each ghost loops back on its end with a period
+    // equal to the time it took to get there. `None` if there are no ghosts.
+    pub fn ghost_steps<S, E>(&self, is_start: S, is_end: E) -> Option<usize>
+    where
+        S: Fn(&str) -> bool,
+        E: Fn(&str) -> bool,
+    {
+        self.labels()
+            .filter(|k| is_start(k))
+            .filter_map(|k| self.steps(k, &is_end))
+            .reduce(|ans, t| t * ans / t.gcd(ans))
     }
-    ans
 }
 
 #[aoc(day8, part1)]
 pub fn part1(input: &str) -> usize {
-    let mut lines = input.lines();
-    let dirs: Vec<Direction> = lines.next().unwrap().chars().map(char::into).collect();
-    let reg = Regex::new(r"(\w\w\w) = \((\w\w\w), (\w\w\w)\)").unwrap();
-    let nodes: HashMap<_, _> = lines
-        .skip(1)
-        .map(|l| {
-            let m = reg.captures(l).unwrap();
-            (
-                m.get(1).unwrap().as_str(),
-                (m.get(2).unwrap().as_str(), m.get(3).unwrap().as_str()),
-            )
-        })
-        .collect();
-    traverse_single(&dirs, &nodes)
+    let network = Network::with_alphabet(input, LEFT_RIGHT).unwrap();
+    network.steps("AAA", |n| n == "ZZZ").unwrap()
 }
 
 #[aoc(day8, part2)]
 pub fn part2(input: &str) -> usize {
-    let mut lines = input.lines();
-    let dirs: Vec<Direction> = lines.next().unwrap().chars().map(char::into).collect();
-    let reg = Regex::new(r"(\w\w\w) = \((\w\w\w), (\w\w\w)\)").unwrap();
-    let nodes: HashMap<_, _> = lines
-        .skip(1)
-        .map(|l| {
-            let m = reg.captures(l).unwrap();
-            (
-                m.get(1).unwrap().as_str(),
-                (m.get(2).unwrap().as_str(), m.get(3).unwrap().as_str()),
-            )
-        })
-        .collect();
-    traverse_ghost(&dirs, &nodes)
+    let network = Network::with_alphabet(input, LEFT_RIGHT).unwrap();
+    network
+        .ghost_steps(|n| n.ends_with('A'), |n| n.ends_with('Z'))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{part1, part2, Network, LEFT_RIGHT};
+
+    #[test]
+    pub fn examples() {
+        let a = "RL\n\nAAA = (BBB, CCC)\nBBB = (DDD, EEE)\nCCC = (ZZZ, GGG)\nDDD = (DDD, DDD)\nEEE = (EEE, EEE)\nGGG = (GGG, GGG)\nZZZ = (ZZZ, ZZZ)";
+        assert_eq!(part1(a), 2);
+        let b = "LR\n\n11A = (11B, XXX)\n11B = (XXX, 11Z)\n11Z = (11B, XXX)\n22A = (22B, XXX)\n22B = (22C, 22C)\n22C = (22Z, 22Z)\n22Z = (22B, 22B)\nXXX = (XXX, XXX)";
+        assert_eq!(part2(b), 6);
+    }
+
+    #[test]
+    pub fn alphabets() {
+        let c = "201\n\nSTART = (X, Y, END)\nX = (X, X, X)\nY = (Y, END, Y)\nEND = (END, END, END)";
+        let network = Network::with_alphabet(c, "012").unwrap();
+        assert_eq!(network.steps("START", |s| s == "END"), Some(1));
+        assert_eq!(network.steps("NOWHERE", |s| s == "END"), None);
+        // The third instruction needs three successors
+        assert!(Network::with_alphabet("02\n\nA = (A, A)", "012").is_none());
+        assert!(Network::with_alphabet("LQ\n\nA = (A, A)", LEFT_RIGHT).is_none());
+    }
+
+    #[test]
+    pub fn malformed() {
+        assert!(Network::with_alphabet("\n\nAAA = (AAA, AAA)", LEFT_RIGHT).is_none());
+        assert!(Network::with_alphabet("LR\n\nAAA = (BBB, CCC)", LEFT_RIGHT).is_none());
+        assert!(
+            Network::with_alphabet("LR\n\nAAA = (AAA, AAA)\nAAA = (AAA, AAA)", LEFT_RIGHT)
+                .is_none()
+        );
+        assert!(Network::with_alphabet("LR\n\nAAA = AAA", LEFT_RIGHT).is_none());
+        let network = Network::with_alphabet("LR\n\nAAA = (AAA, AAA)", LEFT_RIGHT).unwrap();
+        assert_eq!(network.ghost_steps(|l| l == "QQQ", |_| true), None);
+    }
 }
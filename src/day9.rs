@@ -1,41 +1,131 @@
+use std::collections::BTreeMap;
+
 use aoc_runner_derive::{aoc, aoc_generator};
 
-fn diff(hist: &[isize]) -> Vec<Vec<isize>> {
-    let mut out = vec![hist.to_vec()];
-    let mut seed = hist;
-    loop {
-        let diff: Vec<_> = seed.windows(2).map(|w| w[1] - w[0]).collect();
-        let done = diff.iter().all(|&d| d == 0);
-        out.push(diff);
-        if done {
-            break out;
+/// A history fitted to its exact polynomial, stored in Newton forward-difference
+/// form: `f(k) = sum_j C(k, j) * coefficients[j]`, where `coefficients[j]` is
+/// the first entry of the j-th difference row.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Polynomial {
+    coefficients: Vec<i128>,
+    samples: usize,
+}
+
+impl Polynomial {
+    /// Fits `hist` by repeatedly differencing a single row in place, keeping
+    /// only its leading entry. Returns `None` if a difference overflows.
+    #[must_use]
+    pub fn fit(hist: &[isize]) -> Option<Self> {
+        let mut row: Vec<i128> = hist.iter().map(|&h| h as i128).collect();
+        let mut coefficients = Vec::with_capacity(row.len());
+        while !row.iter().all(|&d| d == 0) {
+            coefficients.push(row[0]);
+            for i in 0..row.len() - 1 {
+                row[i] = row[i + 1].checked_sub(row[i])?;
+            }
+            row.pop();
+        }
+        Some(Polynomial {
+            coefficients,
+            samples: hist.len(),
+        })
+    }
+
+    /// The Newton forward-difference coefficients, lowest order first
+    #[must_use]
+    pub fn coefficients(&self) -> &[i128] {
+        &self.coefficients
+    }
+
+    /// The degree of the polynomial; the zero polynomial is reported as 0
+    #[must_use]
+    pub fn degree(&self) -> usize {
+        self.coefficients.len().saturating_sub(1)
+    }
+
+    /// Whether the history actually reached an all-zero difference row. If it
+    /// didn't, the fit is just the unique interpolant through every sample and
+    /// says nothing about the next one.
+    #[must_use]
+    pub fn is_confirmed(&self) -> bool {
+        self.coefficients.len() < self.samples
+    }
+
+    /// How many samples it takes to confirm a polynomial of this degree: one
+    /// per coefficient, plus one more to see the zero row.
+    #[must_use]
+    pub fn samples_needed(&self) -> usize {
+        self.coefficients.len() + 1
+    }
+
+    /// Evaluates the polynomial at index `k`, where 0 is the first sample of
+    /// the history. Negative indices extrapolate backwards. Returns `None` on
+    /// overflow.
+    #[must_use]
+    pub fn eval(&self, k: i128) -> Option<i128> {
+        // binom is C(k, j), using the generalized definition so negative k works
+        let mut binom: i128 = 1;
+        let mut total: i128 = 0;
+        for (j, &coeff) in self.coefficients.iter().enumerate() {
+            if j > 0 {
+                let j = j as i128;
+                // C(k, j) = C(k, j - 1) * (k - j + 1) / j, and the division is exact
+                binom = binom.checked_mul(k.checked_sub(j - 1)?)? / j;
+            }
+            total = total.checked_add(binom.checked_mul(coeff)?)?;
         }
-        seed = out.last().unwrap();
+        Some(total)
     }
 }
 
-fn build_out(diffs: Vec<Vec<isize>>) -> Vec<Vec<isize>> {
-    let mut diffs = diffs;
-    diffs.reverse();
-    diffs[0].push(0);
-    let len = diffs.len();
-    for d in 1..len {
-        let tmp = diffs[d].last().unwrap() + diffs[d - 1].last().unwrap();
-        diffs[d].push(tmp);
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Summary {
+    pub histories: usize,
+    /// Indices of histories that never reached an all-zero row
+    pub unconfirmed: Vec<usize>,
+    /// Number of confirmed histories of each degree
+    pub degrees: BTreeMap<usize, usize>,
+}
+
+impl Summary {
+    #[must_use]
+    pub fn analyze(hists: &[Vec<isize>]) -> Self {
+        let mut summary = Summary {
+            histories: hists.len(),
+            ..Default::default()
+        };
+        for (i, h) in hists.iter().enumerate() {
+            match Polynomial::fit(h) {
+                Some(p) if p.is_confirmed() => *summary.degrees.entry(p.degree()).or_default() += 1,
+                _ => summary.unconfirmed.push(i),
+            }
+        }
+        summary
+    }
+
+    #[must_use]
+    pub fn max_degree(&self) -> Option<usize> {
+        self.degrees.keys().next_back().copied()
     }
-    diffs
 }
 
-fn build_in(diffs: Vec<Vec<isize>>) -> Vec<Vec<isize>> {
-    let mut diffs = diffs;
-    diffs.reverse();
-    diffs[0].insert(0, 0);
-    let len = diffs.len();
-    for d in 1..len {
-        let tmp = diffs[d].first().unwrap() - diffs[d - 1].first().unwrap();
-        diffs[d].insert(0, tmp);
+impl std::fmt::Display for Summary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{} histories, {} polynomial, {} need more samples",
+            self.histories,
+            self.histories - self.unconfirmed.len(),
+            self.unconfirmed.len()
+        )?;
+        for (degree, count) in &self.degrees {
+            writeln!(f, "  degree {degree}: {count}")?;
+        }
+        for i in &self.unconfirmed {
+            writeln!(f, "  history {i} is unconfirmed")?;
+        }
+        Ok(())
     }
-    diffs
 }
 
 #[aoc_generator(day9)]
@@ -48,20 +138,44 @@ pub fn gen(input: &str) -> Vec<Vec<isize>> {
 
 #[aoc(day9, part1)]
 pub fn part1(hists: &[Vec<isize>]) -> isize {
-    hists
+    let total: i128 = hists
         .iter()
-        .map(|h| diff(h))
-        .map(build_out)
-        .map(|d| *d.last().unwrap().last().unwrap())
-        .sum()
+        .map(|h| Polynomial::fit(h).unwrap().eval(h.len() as i128).unwrap())
+        .sum();
+    total.try_into().unwrap()
 }
 
 #[aoc(day9, part2)]
 pub fn part2(hists: &[Vec<isize>]) -> isize {
-    hists
+    let total: i128 = hists
         .iter()
-        .map(|h| diff(h))
-        .map(build_in)
-        .map(|d| *d.last().unwrap().first().unwrap())
-        .sum()
+        .map(|h| Polynomial::fit(h).unwrap().eval(-1).unwrap())
+        .sum();
+    total.try_into().unwrap()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Polynomial, Summary};
+
+    #[test]
+    pub fn extrapolation() {
+        let p = Polynomial::fit(&[1, 3, 6, 10, 15, 21]).unwrap();
+        assert_eq!(p.degree(), 2);
+        assert_eq!(p.coefficients(), &[1, 2, 1]);
+        assert_eq!(p.eval(6), Some(28));
+        assert_eq!(p.eval(-1), Some(0));
+        assert_eq!(p.eval(1_000), Some(1_001 * 1_002 / 2));
+        assert_eq!(Polynomial::fit(&[0, 1, 4]).unwrap().eval(i128::MAX), None);
+    }
+
+    #[test]
+    pub fn unconfirmed() {
+        let hists = vec![vec![0, 3, 6, 9, 12, 15], vec![1, 2, 4, 8], vec![7, 7]];
+        assert!(!Polynomial::fit(&hists[1]).unwrap().is_confirmed());
+        let summary = Summary::analyze(&hists);
+        assert_eq!(summary.unconfirmed, vec![1]);
+        assert_eq!(summary.max_degree(), Some(1));
+        assert_eq!(summary.degrees.get(&0), Some(&1));
+    }
 }
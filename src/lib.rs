@@ -1,5 +1,6 @@
 use aoc_runner_derive::aoc_lib;
 
+pub mod cycle;
 pub mod day1;
 pub mod day10;
 pub mod day11;